@@ -0,0 +1,14 @@
+#!/usr/bin/env python3
+# Copyright 2022-2024, Collabora, Ltd.
+#
+# SPDX-License-Identifier: BSL-1.0
+#
+# Author: Rylie Pavlik <rylie.pavlik@collabora.com>
+"""Check that the GitLab URL, token, and projects are set up, without touching any board."""
+
+import sys
+
+from openxr_ops.gitlab import check_config
+
+if __name__ == "__main__":
+    sys.exit(0 if check_config() else 1)
@@ -6,18 +6,38 @@
 # Author: Rylie Pavlik <rylie.pavlik@collabora.com>
 """This updates a CTS workboard, but starting with the board, rather than GitLab."""
 
+import copy
 import itertools
 import json
 import logging
-from typing import Union, cast
+import os
+import sys
+import tomllib
+from typing import Any, Dict, List, Optional, Tuple, Union, cast
 
 import gitlab
 import gitlab.v4.objects
 from gitlab.v4.objects import ProjectIssue, ProjectMergeRequest
 
-from nullboard_gitlab import ListName, parse_board, update_board
+from nullboard_gitlab import (
+    UNICODE_BULLET_AND_SPACE,
+    ListName,
+    diff_boards,
+    drop_deleted_notes,
+    increment_revision,
+    list_completion_ratio,
+    parse_board,
+    reconcile_board,
+    update_boards,
+)
 from openxr_ops.gitlab import OpenXRGitlab
-from work_item_and_collection import WorkUnit, WorkUnitCollection, get_short_ref
+from work_item_and_collection import (
+    DiscoveryConfig,
+    WorkUnit,
+    WorkUnitCollection,
+    discover_work,
+    get_short_ref,
+)
 
 # List stuff that causes undesired merging here
 # Anything on this list will be excluded from the board
@@ -44,6 +64,9 @@ SKIP_RELATED_MR_LOOKUP = DO_NOT_MERGE.union(
     }
 )
 
+DEFAULT_CONFIG_FILENAME = "workboard.toml"
+"""Config file read for option defaults, if --config is not given"""
+
 # Must have at least one of these labels to show up on this board
 # since there are now two projects using "contractor approved"
 REQUIRED_LABEL_SET = set(
@@ -99,72 +122,115 @@ def _make_api_item_text(
     )
 
 
-def make_note_text(item: WorkUnit, item_formatter) -> str:
+def make_note_text(
+    item: WorkUnit, item_formatter, bullet: str = UNICODE_BULLET_AND_SPACE
+) -> str:
     return "{key_item}\n{rest}".format(
         key_item=item_formatter(
             item.key_item,
         ),
         rest="\n".join(
-            "• {}".format(
+            "{}{}".format(
+                bullet,
                 item_formatter(
                     api_item,
-                )
+                ),
             )
             for api_item in item.non_key_issues_and_mrs()
         ),
     )
 
 
-def main(in_filename, out_filename):
+def _check_failed_refs(work: WorkUnitCollection, strict: bool) -> int:
+    """Log any refs that could not be retrieved, returning the exit code."""
+    log = logging.getLogger(__name__)
+    if not work.failed_refs:
+        return 0
+    log.log(
+        logging.ERROR if strict else logging.WARNING,
+        "Could not retrieve %d refs: %s",
+        len(work.failed_refs),
+        ", ".join(work.failed_refs),
+    )
+    return 1 if strict else 0
+
+
+def _config_to_argv(config: Dict[str, Any]) -> List[str]:
+    """Turn the options in a config file into command line arguments."""
+    argv = []
+    for key, value in config.items():
+        option = "--" + key.replace("_", "-")
+        if isinstance(value, bool):
+            argv.append(option if value else f"--no-{option[2:]}")
+        elif isinstance(value, list):
+            for entry in value:
+                if isinstance(entry, list):
+                    argv.append(option)
+                    argv.extend(str(part) for part in entry)
+                else:
+                    argv.append(f"{option}={entry}")
+        else:
+            argv.append(f"{option}={value}")
+    return argv
+
+
+def main(
+    in_filename,
+    out_filename,
+    preview: bool = False,
+    max_new_notes: Optional[int] = None,
+    ignore_refs: Optional[List[str]] = None,
+    extra_boards: Optional[List[Tuple[str, str]]] = None,
+    dedupe_across_boards: bool = False,
+    strict: bool = False,
+    drop_deleted: bool = False,
+    put_refs_first: bool = False,
+    plan_json_filename: Optional[str] = None,
+    bullet: str = UNICODE_BULLET_AND_SPACE,
+    history_limit: Optional[int] = None,
+) -> int:
     logging.basicConfig(level=logging.INFO)
 
     log = logging.getLogger(__name__)
     work = WorkUnitCollection()
-    work.do_not_merge = DO_NOT_MERGE
+    extra_ignored = set(ignore_refs or [])
+    work.do_not_merge = DO_NOT_MERGE.union(extra_ignored)
 
     oxr_gitlab = OpenXRGitlab.create()
 
     proj = oxr_gitlab.main_proj
 
-    log.info("Reading %s", in_filename)
-    with open(in_filename, "r", encoding="utf-8") as fp:
-        existing_board = json.load(fp)
-
-    log.info("Parsing board loaded from %s", in_filename)
-    parse_board(proj, work, existing_board)
+    board_files = [(in_filename, out_filename)] + list(extra_boards or [])
+    boards = []
+    # As read, to compare against after the changes made here before updating
+    loaded_boards = []
+    for board_in_filename, _ in board_files:
+        log.info("Reading %s", board_in_filename)
+        with open(board_in_filename, "r", encoding="utf-8") as fp:
+            existing_board = json.load(fp)
+        loaded_boards.append(copy.deepcopy(existing_board))
+
+        if drop_deleted:
+            log.info("Checking refs on board loaded from %s", board_in_filename)
+            findings = reconcile_board(proj, existing_board)
+            for text in drop_deleted_notes(existing_board, findings):
+                log.info("Dropped note for deleted items: %s", text.split("\n")[0])
+
+        log.info("Parsing board loaded from %s", board_in_filename)
+        parse_board(proj, work, existing_board)
+        boards.append(existing_board)
 
     # Grab all "Contractor Approved Backlog" issues.
     log.info("Handling GitLab issues")
-    for issue in proj.issues.list(
-        labels=["Contractor Approved Backlog"], state="opened", iterator=True
-    ):
-        proj_issue = cast(gitlab.v4.objects.ProjectIssue, issue)
-        ref = get_short_ref(proj_issue)
-        issue_labels = set(proj_issue.attributes["labels"])
-        if not issue_labels.intersection(REQUIRED_LABEL_SET):
-            log.info(
-                "Skipping contractor approved but non-CTS issue: %s: %s  %s",
-                ref,
-                proj_issue.title,
-                proj_issue.attributes["web_url"],
-            )
-            continue
-
-        if ref in SKIP_RELATED_MR_LOOKUP:
-            log.info(
-                "Skipping GitLab Issue Search for: %s: %s",
-                ref,
-                proj_issue.title,
-            )
-            continue
-        refs = [ref]
-        refs.extend(
-            mr["references"]["short"]  # type: ignore
-            for mr in proj_issue.related_merge_requests()
-        )
+    discovery = DiscoveryConfig(
+        labels=["Contractor Approved Backlog"],
+        required_label_set=REQUIRED_LABEL_SET,
+        skip_refs=SKIP_RELATED_MR_LOOKUP.union(extra_ignored),
+    )
+    for proj_issue, refs in discover_work(proj, discovery):
         log.info(
             "GitLab Issue Search: %s: %s  (refs: %s)",
-            ref,
+            refs[0],
             proj_issue.title,
             ",".join(refs),
         )
@@ -187,29 +253,186 @@ def main(in_filename, out_filename):
             log.info("Skipping release candidate MR %s: %s", ref, proj_mr.title)
             continue
         log.info("GitLab MR Search: %s: %s", ref, proj_mr.title)
-        work.add_refs(proj, [ref])
+        if work.attach_mr_to_workunit(proj_mr) is None:
+            work.add_refs(proj, [ref])
+
+    log.info("Work unit collection: %s", work.stats())
+    for item in work.completed_items():
+        log.warning("Work unit %s has no open items: %s", item.ref, item.title)
 
-    log.info("Updating board with the latest data")
-    updated = update_board(
-        work,
-        existing_board,
+    update_kwargs = dict(
         list_titles_to_skip_adding_to=[ListName.DONE],
-        note_text_maker=lambda x: make_note_text(x, _make_api_item_text),
+        note_text_maker=lambda x: make_note_text(
+            x, _make_api_item_text, bullet=bullet
+        ),
+        max_new_notes=max_new_notes,
+        dedupe_across_boards=dedupe_across_boards,
+        history_limit=history_limit,
+        put_refs_first=put_refs_first,
     )
 
-    log.info("Writing output file %s", out_filename)
-    with open(out_filename, "w", encoding="utf-8") as fp:
-        json.dump(existing_board, fp, indent=4)
+    if preview or plan_json_filename:
+        proposed = copy.deepcopy(boards)
+        update_boards(work, proposed, **update_kwargs)
+        plan = []
+        for (board_in_filename, _), old, new in zip(
+            board_files, loaded_boards, proposed
+        ):
+            log.info("Previewing changes to %s, not writing output", board_in_filename)
+            changes = diff_boards(old, new)
+            plan.append(
+                {
+                    "board": board_in_filename,
+                    "changes": [change.to_json() for change in changes],
+                }
+            )
+            if not preview:
+                continue
+            for change in changes:
+                print(f"## {change.list_title}")
+                print(f"--- {change.old_text}")
+                print(f"+++ {change.new_text}")
+                print()
+        if plan_json_filename:
+            log.info("Writing proposed changes to %s", plan_json_filename)
+            with open(plan_json_filename, "w", encoding="utf-8") as fp:
+                json.dump(plan, fp, indent=4)
+        return _check_failed_refs(work, strict)
+
+    log.info("Updating boards with the latest data")
+    results = update_boards(work, boards, **update_kwargs)
+
+    for (_, board_out_filename), existing_board, loaded_board, updated in zip(
+        board_files, boards, loaded_boards, results
+    ):
+        if not updated and existing_board != loaded_board:
+            # Only changed before updating, as by --drop-deleted
+            increment_revision(existing_board, history_limit)
+            updated = True
 
-    if updated:
-        log.info("Board contents have been changed.")
-    else:
-        log.info("No changes to board, output is the same data as input.")
+        for notelist in existing_board["lists"]:
+            title = notelist["title"]
+            log.info(
+                "%s: %d%% complete", title, 100 * list_completion_ratio(work, title)
+            )
+
+        log.info("Writing output file %s", board_out_filename)
+        with open(board_out_filename, "w", encoding="utf-8") as fp:
+            json.dump(existing_board, fp, indent=4)
+
+        if updated:
+            log.info("Board contents have been changed.")
+        else:
+            log.info("No changes to board, output is the same data as input.")
+
+    return _check_failed_refs(work, strict)
 
 
 if __name__ == "__main__":
-    main(
+    import argparse
+
+    parser = argparse.ArgumentParser()
+    parser.add_argument(
+        "--preview",
+        action=argparse.BooleanOptionalAction,
+        help="Print the note text that would change instead of writing the output",
+    )
+    parser.add_argument(
+        "--max-new-notes",
+        type=int,
+        help="Add at most this many new notes, leaving the rest for a later run",
+    )
+    parser.add_argument(
+        "--ignore-ref",
+        type=str,
+        action="append",
+        help="Ref like #123 or !456 to exclude, like those in DO_NOT_MERGE",
+    )
+    parser.add_argument(
+        "--extra-board",
+        nargs=2,
+        metavar=("INPUT", "OUTPUT"),
+        action="append",
+        help="Also update this board, sharing work units with the main board",
+    )
+    parser.add_argument(
+        "--dedupe-across-boards",
+        action=argparse.BooleanOptionalAction,
+        help="Keep each work unit on only the first board that has it",
+    )
+    parser.add_argument(
+        "--strict",
+        action=argparse.BooleanOptionalAction,
+        help="Exit with an error if any ref could not be retrieved",
+    )
+    parser.add_argument(
+        "--drop-deleted",
+        action=argparse.BooleanOptionalAction,
+        help="Check every ref first, removing notes for deleted issues and MRs",
+    )
+    parser.add_argument(
+        "--refs-first",
+        action=argparse.BooleanOptionalAction,
+        help="Move the lines of each note that have refs above its other lines",
+    )
+    parser.add_argument(
+        "--plan-json",
+        type=str,
+        help="Write the changes that would be made to this JSON file, "
+        "instead of writing the output",
+    )
+    parser.add_argument(
+        "--bullet",
+        type=str,
+        default=UNICODE_BULLET_AND_SPACE,
+        help="Prefix for the lines after the first in each note, "
+        f"including any space (default: '{UNICODE_BULLET_AND_SPACE}')",
+    )
+    parser.add_argument(
+        "--history-limit",
+        type=int,
+        help="Record each new revision in the board history, keeping this many "
+        "entries (the history is left alone if not given)",
+    )
+    parser.add_argument(
+        "--config",
+        type=str,
+        help="TOML file with defaults for these options, "
+        f"used if present (default: {DEFAULT_CONFIG_FILENAME})",
+        default=DEFAULT_CONFIG_FILENAME,
+    )
+
+    args = parser.parse_args()
+    if os.path.exists(args.config):
+        with open(args.config, "rb") as fp:
+            config = tomllib.load(fp)
+        dests = {key.replace("-", "_") for key in config}
+        unknown = dests - set(vars(args))
+        if unknown:
+            parser.error(
+                f"Unknown options in {args.config}: {', '.join(sorted(unknown))}"
+            )
+        # Parsed like the command line, so values are checked and converted the same
+        config_args = parser.parse_args(_config_to_argv(config))
+        for dest in dests:
+            # Options given on the command line win over the config file
+            if getattr(args, dest) == parser.get_default(dest):
+                setattr(args, dest, getattr(config_args, dest))
+
+    result = main(
         # "/home/ryan/Downloads/Nullboard-1661530413298-OpenXR-CTS.nbx",
         "Nullboard-1661530413298-OpenXR-CTS.nbx",
         "Nullboard-1661530413298-OpenXR-CTS-updated.nbx",
+        preview=args.preview,
+        max_new_notes=args.max_new_notes,
+        ignore_refs=args.ignore_ref,
+        extra_boards=args.extra_board,
+        dedupe_across_boards=args.dedupe_across_boards,
+        strict=args.strict,
+        drop_deleted=args.drop_deleted,
+        put_refs_first=args.refs_first,
+        plan_json_filename=args.plan_json,
+        bullet=args.bullet,
+        history_limit=args.history_limit,
     )
+    sys.exit(result)
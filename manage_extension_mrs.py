@@ -51,6 +51,13 @@ if __name__ == "__main__":
         help="Update the ticket corresponding to the MR to NeedsChampionApprovalOrRatification",
     )
 
+    parser.add_argument(
+        "--checklist-label",
+        type=str,
+        action="append",
+        help="Label identifying release checklists (repeatable), defaults to 'Release Checklist'",
+    )
+
     args = parser.parse_args()
     logging.basicConfig(level=logging.INFO)
 
@@ -63,6 +70,7 @@ if __name__ == "__main__":
         oxr_gitlab.operations_proj,
         checklist_factory=None,
         vendor_names=VendorNames.from_git(oxr_gitlab.main_proj),
+        checklist_labels=args.checklist_label,
     )
 
     try:
@@ -5,19 +5,71 @@
 #
 # Author: Rylie Pavlik <rylie.pavlik@collabora.com>
 
+import copy
+import difflib
+import json
 import logging
 import re
 import time
 from dataclasses import dataclass
+from datetime import datetime, timezone
+from enum import Enum
 from typing import Any, Callable, Dict, Generator, List, Optional, Set, Tuple, Union
 
 import gitlab
+import gitlab.exceptions
 import gitlab.v4.objects
 from gitlab.v4.objects import ProjectIssue, ProjectMergeRequest
 
-from work_item_and_collection import WorkUnit, WorkUnitCollection
+from work_item_and_collection import (
+    ReferenceType,
+    WorkUnit,
+    WorkUnitCollection,
+    get_issue_from_data_or_project,
+    get_mr_from_data_or_project,
+    ref_sort_key,
+)
 
-_REF_RE = re.compile(r"([!#][0-9]+)\b")
+# The optional end number is only used when expanding ranges like "#1-5"
+_REF_RE = re.compile(
+    r"(?P<symbol>[!#])(?P<start>[1-9][0-9]*)(?:-(?P<end>[1-9][0-9]*))?\b"
+)
+
+_LENIENT_REF_RE = re.compile(
+    r"(?P<symbol>[!#])(?P<start>[0-9]+)(?:-(?P<end>[0-9]+))?\b"
+)
+
+MAX_REF_RANGE_LENGTH = 100
+"""Longest range like "#1-5" that find_refs will expand"""
+
+DEFAULT_MAX_REFS_PER_LINE = 64
+"""Most refs find_refs will take from one line, unless told otherwise"""
+
+UNICODE_BULLET_AND_SPACE = "• "
+"""Default prefix for the lines after the first in a note"""
+
+# A Markdown-style heading: the space after the hashes keeps this distinct from "#123"
+_HEADING_RE = re.compile(r"^(?P<level>#{1,6}) (?P<title>.*)$")
+
+# A ref wrapped in a Markdown link, as written by _make_api_item_text
+_LINKED_REF_RE = re.compile(r"\[(?P<ref>[!#][1-9][0-9]*)\]\([^)\s]*\)")
+
+# A manual status annotation, like "#12 [status:blocked]", kept when updating lines
+_STATUS_OVERRIDE_RE = re.compile(r"\[status:(?P<status>[^\]]+)\]")
+
+# The flag drop_deleted_notes puts after a deleted ref, like "#12 [deleted]"
+_DELETED_FLAG_RE = re.compile(r"\s*\[deleted\]")
+
+# The line added by truncate_note_text in place of the lines it drops
+_MORE_LINES_RE = re.compile(r"^\(\+(?P<count>[0-9]+) more\)$")
+
+# The work unit size line added by make_note_text(show_unit_size=True)
+_UNIT_SIZE_RE = re.compile(r"^\((?P<count>[0-9]+) linked items\)$")
+
+_ACTION_RE = re.compile(
+    r"^(?:[^\w\s]+ )?(?P<keyword>fixes|closes|resolves|relates to)\s+[!#][0-9]",
+    re.IGNORECASE,
+)
 
 
 class ListName:
@@ -41,11 +93,22 @@ def guess_list(item: WorkUnit) -> str:
     return ListName.TODO
 
 
+def format_age(created_at: str, now: Optional[datetime] = None) -> str:
+    """Describe the age of an ISO 8601 creation timestamp, like "opened 45d ago"."""
+    created = datetime.fromisoformat(created_at.replace("Z", "+00:00"))
+    if now is None:
+        now = datetime.now(timezone.utc)
+    return f"opened {(now - created).days}d ago"
+
+
 def _make_api_item_text(
     api_item: Union[ProjectIssue, ProjectMergeRequest],
     show_votes: bool = False,
     show_mr_votes: bool = False,
     show_objection_window: bool = False,
+    show_updated: bool = False,
+    blocked_by_work: Optional[WorkUnitCollection] = None,
+    show_age: bool = False,
 ) -> str:
     state = []
     if api_item.state == "closed":
@@ -74,11 +137,32 @@ def _make_api_item_text(
 
     state_str = " ".join(state)
 
-    return "[{ref}]({url}): {state}{title}".format(
-        ref=api_item.references["short"],
+    updated_str = ""
+    updated_at = getattr(api_item, "updated_at", None)
+    if show_updated and updated_at:
+        # Just the date part of the ISO 8601 timestamp
+        updated_str = f" (updated {updated_at[:10]})"
+
+    age_str = ""
+    created_at = getattr(api_item, "created_at", None)
+    if show_age and created_at:
+        age_str = f" ({format_age(created_at)})"
+
+    blocked_str = ""
+    ref = api_item.references["short"]
+    if blocked_by_work is not None and not is_mr and api_item.state == "opened":
+        blockers = blocked_by_work.get_tracked_blockers(api_item)
+        if blockers:
+            blocked_str = " (blocked by {})".format(", ".join(blockers))
+
+    return "[{ref}]({url}): {state}{title}{updated}{age}{blocked}".format(
+        ref=ref,
         state=state_str,
         title=api_item.title,
         url=api_item.web_url,
+        updated=updated_str,
+        age=age_str,
+        blocked=blocked_str,
     )
 
 
@@ -87,33 +171,207 @@ def make_item_bullet(
     show_votes: bool = False,
     show_mr_votes: bool = False,
     show_objection_window: bool = False,
+    bullet: str = UNICODE_BULLET_AND_SPACE,
+    show_updated: bool = False,
+    blocked_by_work: Optional[WorkUnitCollection] = None,
+    show_age: bool = False,
 ) -> str:
-    return "• {}".format(
+    return "{}{}".format(
+        bullet,
         _make_api_item_text(
             api_item,
             show_votes=show_votes,
             show_mr_votes=show_mr_votes,
             show_objection_window=show_objection_window,
-        )
+            show_updated=show_updated,
+            blocked_by_work=blocked_by_work,
+            show_age=show_age,
+        ),
     )
 
 
+def strip_bullet(line: str, bullet: str = UNICODE_BULLET_AND_SPACE) -> str:
+    """
+    Remove the bullet added by make_item_bullet from a note line, and trim spaces.
+
+    As with str.strip, the characters of the bullet are taken off both ends.
+    """
+    return line.strip(bullet.strip()).strip()
+
+
+class ActionKeyword(Enum):
+    """Verbs that may lead a reference in a note line."""
+
+    FIXES = "fixes"
+    CLOSES = "closes"
+    RESOLVES = "resolves"
+    RELATES_TO = "relates to"
+
+
+def _refs_for_match(match: re.Match, expand_ranges: bool) -> List[str]:
+    log = logging.getLogger(__name__)
+    symbol = match.group("symbol")
+    start = int(match.group("start"))
+    if start == 0:
+        return []
+    end = match.group("end")
+    if not expand_ranges or end is None:
+        return [f"{symbol}{start}"]
+    end = int(end)
+    if end < start or end - start + 1 > MAX_REF_RANGE_LENGTH:
+        log.warning("Not expanding reference range %s", match.group(0))
+        return [f"{symbol}{start}"]
+    return [f"{symbol}{num}" for num in range(start, end + 1)]
+
+
+def find_refs(
+    text: str,
+    allow_leading_zeros: bool = False,
+    expand_ranges: bool = False,
+    require_leading_space: bool = False,
+    max_refs_per_line: Optional[int] = DEFAULT_MAX_REFS_PER_LINE,
+) -> List[str]:
+    """
+    Extract short issue/MR refs like "#123" and "!45" from text.
+
+    With allow_leading_zeros, a ref like "#012" is also accepted,
+    and normalized to "#12".
+
+    With expand_ranges, a range like "#1-3" becomes "#1", "#2", "#3".
+    Ranges longer than MAX_REF_RANGE_LENGTH are not expanded.
+
+    With require_leading_space, a ref must start the text or follow whitespace
+    or an opening bracket, so "done!12 times" has no refs but "[!12](...)" does.
+
+    At most max_refs_per_line refs are taken from each line, with a warning
+    if there were more. Pass None for no limit.
+
+    Refs flagged by drop_deleted_notes, like "#12 [deleted]", are skipped.
+    """
+    log = logging.getLogger(__name__)
+    ref_re = _LENIENT_REF_RE if allow_leading_zeros else _REF_RE
+    refs = []
+    for line in text.split("\n"):
+        line_refs: List[str] = []
+        for match in ref_re.finditer(line):
+            if require_leading_space and match.start() > 0:
+                preceding = line[match.start() - 1]
+                if not preceding.isspace() and preceding not in "([":
+                    continue
+            if _DELETED_FLAG_RE.match(line, match.end()):
+                continue
+            line_refs.extend(_refs_for_match(match, expand_ranges))
+            if max_refs_per_line is not None and len(line_refs) > max_refs_per_line:
+                log.warning(
+                    "Only using the first %d refs in line: '%s'",
+                    max_refs_per_line,
+                    line[:80],
+                )
+                line_refs = line_refs[:max_refs_per_line]
+                break
+        refs.extend(line_refs)
+    return refs
+
+
+@dataclass
+class RefFix:
+    """A probable mistake in writing a ref, with the suggested replacement."""
+
+    start: int
+    end: int
+    original: str
+    replacement: str
+
+
+# Common ways of writing a ref that find_refs does not recognize as intended.
+# "MR !12" and "issue #12" are fine, but "MR #12" and "issue 12" are not.
+_MR_WORD_RE = re.compile(
+    r"\b(?:MR|merge request)\s*(?:#\s*|!\s+)?(?P<num>[1-9][0-9]*)\b", re.IGNORECASE
+)
+_ISSUE_WORD_RE = re.compile(r"\bissue\s*(?:#\s+)?(?P<num>[1-9][0-9]*)\b", re.IGNORECASE)
+_SPACED_REF_RE = re.compile(r"(?<![\w!#])(?P<symbol>[!#]) +(?P<num>[1-9][0-9]*)\b")
+
+
+def suggest_ref_fixes(line: str) -> List[RefFix]:
+    """
+    Find likely-intended refs in a line, like "MR 12", "issue 12", or "# 12".
+
+    Returns suggested fixes in order, or an empty list if nothing looks wrong.
+    """
+    candidates = [
+        (match, "!" + match.group("num")) for match in _MR_WORD_RE.finditer(line)
+    ]
+    candidates.extend(
+        (match, "#" + match.group("num")) for match in _ISSUE_WORD_RE.finditer(line)
+    )
+    if not _HEADING_RE.match(line):
+        candidates.extend(
+            (match, match.group("symbol") + match.group("num"))
+            for match in _SPACED_REF_RE.finditer(line)
+        )
+
+    fixes: List[RefFix] = []
+    for match, replacement in sorted(candidates, key=lambda c: c[0].start()):
+        if fixes and match.start() < fixes[-1].end:
+            # overlaps a fix we already have
+            continue
+        fixes.append(RefFix(match.start(), match.end(), match.group(0), replacement))
+    return fixes
+
+
 @dataclass
 class NoteLine:
     line: str
     ref: Optional[str] = None
+    heading_level: Optional[int] = None
+    """Number of leading # characters, if this line is a Markdown heading"""
+
+    status_override: Optional[str] = None
+    """Manually-specified status from a [status:...] annotation"""
 
     def __str__(self):
         return self.line
 
+    @property
+    def is_heading(self) -> bool:
+        return self.heading_level is not None
+
+    def action_keyword(self) -> Optional[ActionKeyword]:
+        """Return the verb leading the reference in this line, if any."""
+        if not self.ref:
+            return None
+        match = _ACTION_RE.match(self.line)
+        if not match:
+            return None
+        return ActionKeyword(match.group("keyword").casefold())
+
     @classmethod
     def parse_line(cls, s: str) -> "NoteLine":
-        matches = _REF_RE.findall(s)
+        heading = _HEADING_RE.match(s)
+        if heading:
+            return NoteLine(s, heading_level=len(heading.group("level")))
+        matches = find_refs(s)
         if not matches:
             return NoteLine(s)
         if len(matches) > 1:
             logging.warn(f"Found more than one ref in line: '{s}'")
-        return NoteLine(s, str(matches[0]))
+        status = _STATUS_OVERRIDE_RE.search(s)
+        return NoteLine(
+            s,
+            str(matches[0]),
+            status_override=status.group("status") if status else None,
+        )
+
+    def with_status_override(self, status: Optional[str]) -> "NoteLine":
+        """Return a copy of this line carrying the given status annotation."""
+        if status is None or self.status_override == status:
+            return self
+        return NoteLine(
+            f"{self.line} [status:{status}]",
+            self.ref,
+            heading_level=self.heading_level,
+            status_override=status,
+        )
 
 
 def make_note_lines(item: WorkUnit) -> List[NoteLine]:
@@ -127,17 +385,67 @@ def parse_note(note: str) -> List[NoteLine]:
     return [NoteLine.parse_line(line) for line in note.split("\n")]
 
 
+def parse_note_trimmed(note: str) -> List[NoteLine]:
+    """Like parse_note, but a single trailing newline does not add a blank line."""
+    return parse_note(note.removesuffix("\n"))
+
+
+def to_shorthand_text(note_text: str) -> str:
+    """Replace Markdown-linked refs like "[#12](https://...)" with just "#12"."""
+    return _LINKED_REF_RE.sub(r"\g<ref>", note_text)
+
+
+def refs_first(notelines: List[NoteLine]) -> List[NoteLine]:
+    """Move lines with refs ahead of other lines, otherwise keeping their order."""
+    return [nl for nl in notelines if nl.ref] + [nl for nl in notelines if not nl.ref]
+
+
+def collapse_blank_runs(notelines: List[NoteLine]) -> List[NoteLine]:
+    """Reduce each run of consecutive blank lines to a single blank line."""
+    collapsed: List[NoteLine] = []
+    for noteline in notelines:
+        is_blank = not noteline.ref and not noteline.line.strip()
+        if is_blank and collapsed and not collapsed[-1].line.strip():
+            continue
+        collapsed.append(noteline)
+    return collapsed
+
+
+def make_unit_size_line(item: WorkUnit) -> Optional[str]:
+    """Describe how many items the work unit spans, if more than one."""
+    count = len(set(item.refs()))
+    if count < 2:
+        return None
+    return f"({count} linked items)"
+
+
 def merge_note(existing_note: str, notelines: List[NoteLine]) -> str:
     new_lines_by_ref = {nl.ref: nl for nl in notelines if nl.ref}
+    new_unit_size = next(
+        (str(nl) for nl in notelines if _UNIT_SIZE_RE.match(nl.line)), None
+    )
+    # The note text makers put the key item first
+    key_ref = next((nl.ref for nl in notelines if nl.ref), None)
     old_lines = parse_note(existing_note)
     merged_lines = []
+    key_index = 0
     for noteline in old_lines:
+        if key_ref is not None and noteline.ref == key_ref:
+            key_index = len(merged_lines)
+        if _UNIT_SIZE_RE.match(noteline.line):
+            # generated, so always replaced by the new one if any
+            continue
+        if _MORE_LINES_RE.match(noteline.line):
+            # generated by truncate_note_text, which is run again on the result
+            continue
         if not noteline.ref:
             # we can't update this line, there's no ref for it
             merged_lines.append(str(noteline))
             continue
         new_noteline = new_lines_by_ref.get(noteline.ref)
         if new_noteline is not None:
+            # Keep any manual status annotation from the old line
+            new_noteline = new_noteline.with_status_override(noteline.status_override)
             merged_lines.append(str(new_noteline))
             # wipe this out now
             del new_lines_by_ref[noteline.ref]
@@ -146,46 +454,228 @@ def merge_note(existing_note: str, notelines: List[NoteLine]) -> str:
 
     # Add the ones we missed
     for noteline in new_lines_by_ref.values():
+        if noteline.ref == key_ref:
+            key_index = len(merged_lines)
         merged_lines.append(str(noteline))
+
+    if new_unit_size is not None:
+        # Goes right after the key item
+        merged_lines.insert(key_index + 1, new_unit_size)
     return "\n".join(merged_lines)
 
 
+def truncate_note_text(
+    text: str,
+    max_lines: Optional[int] = None,
+    max_chars: Optional[int] = None,
+) -> str:
+    """
+    Drop trailing ref lines of a note that exceeds the limits, adding "(+N more)".
+
+    Only the first line and the lines with refs count toward the limits, and only
+    the lines with refs after the first line are dropped. Other lines are the
+    user's own text, and are always kept.
+    Only whole lines are removed, so a formatted reference link is never split.
+    """
+
+    def fits(lines: List[str]) -> bool:
+        if max_lines is not None and len(lines) > max_lines:
+            return False
+        if max_chars is not None and len("\n".join(lines)) > max_chars:
+            return False
+        return True
+
+    lines = text.split("\n")
+    ref_indices = [i for i, line in enumerate(lines) if i > 0 and find_refs(line)]
+    counted = [lines[0]] + [lines[i] for i in ref_indices]
+    if fits(counted):
+        return text
+
+    keep = len(ref_indices)
+    while keep > 0:
+        keep -= 1
+        trailer = f"(+{len(ref_indices) - keep} more)"
+        if fits(counted[: keep + 1] + [trailer]):
+            break
+
+    dropped = set(ref_indices[keep:])
+    truncated = []
+    for i, line in enumerate(lines):
+        if i not in dropped:
+            truncated.append(line)
+        elif i == ref_indices[keep]:
+            # In place of the first dropped line
+            truncated.append(f"(+{len(dropped)} more)")
+    return "\n".join(truncated)
+
+
 def make_note_text(
     item: WorkUnit,
     show_votes: bool = False,
     show_mr_votes: bool = False,
     show_objection_window: bool = False,
+    sort_refs: bool = False,
+    bullet: str = UNICODE_BULLET_AND_SPACE,
+    show_unit_size: bool = False,
+    show_updated: bool = False,
+    blocked_by_work: Optional[WorkUnitCollection] = None,
+    show_age: bool = False,
 ) -> str:
+    if sort_refs:
+        others = item.sorted_non_key_issues_and_mrs()
+    else:
+        others = list(item.non_key_issues_and_mrs())
+    key_item = _make_api_item_text(
+        item.key_item,
+        show_votes=show_votes,
+        show_mr_votes=show_mr_votes,
+        show_objection_window=show_objection_window,
+        show_updated=show_updated,
+        blocked_by_work=blocked_by_work,
+        show_age=show_age,
+    )
+    unit_size = make_unit_size_line(item) if show_unit_size else None
+    if unit_size:
+        key_item = f"{key_item}\n{unit_size}"
     return "{key_item}\n{rest}".format(
-        key_item=_make_api_item_text(
-            item.key_item,
-            show_votes=show_votes,
-            show_mr_votes=show_mr_votes,
-            show_objection_window=show_objection_window,
-        ),
+        key_item=key_item,
         rest="\n".join(
             make_item_bullet(
                 api_item,
                 show_votes=show_votes,
                 show_mr_votes=show_mr_votes,
                 show_objection_window=show_objection_window,
+                bullet=bullet,
+                show_updated=show_updated,
+                blocked_by_work=blocked_by_work,
+                show_age=show_age,
             )
-            for api_item in item.non_key_issues_and_mrs()
+            for api_item in others
         ),
     )
 
 
-def make_empty_board(title):
+def list_completion_ratio(work: WorkUnitCollection, list_name: str) -> float:
+    """
+    Return the fraction of issues and MRs in the work units on the named list
+    that are done, as by WorkUnit.is_item_done, or 0.0 if there are none.
+    """
+    total = 0
+    done = 0
+    for item in work.items:
+        if item.list_name != list_name:
+            continue
+        for api_item in item.all_issues_and_mrs():
+            total += 1
+            if item.is_item_done(api_item):
+                done += 1
+    if total == 0:
+        return 0.0
+    return done / total
+
+
+def make_secondary_note_text(existing_note: str, item: WorkUnit) -> str:
+    """
+    Rewrite a note that duplicates a work unit shown on another card.
+
+    Lines for refs in the work unit are replaced by a single "See ..." line
+    pointing at the key item, other lines are kept.
+    """
+    item_refs = set(item.refs())
+    see_line = "See {}".format(_make_api_item_text(item.key_item))
+    lines = []
+    for noteline in parse_note(existing_note):
+        if noteline.ref in item_refs:
+            if see_line not in lines:
+                lines.append(see_line)
+            continue
+        lines.append(str(noteline))
+    return "\n".join(lines)
+
+
+def make_text_note(lines: List[str], raw: bool = False) -> Dict[str, Any]:
+    """
+    Make a note containing just the given lines of text, such as a section header.
+
+    Pass raw=True for a nullboard "raw" note, shown as a sub-header.
+    """
+    note: Dict[str, Any] = {"text": "\n".join(lines)}
+    if raw:
+        note["raw"] = True
+    return note
+
+
+def make_empty_board(title, board_id: Optional[int] = None):
+    """
+    Create a board with no lists.
+
+    The id defaults to the current time, pass board_id for reproducible output.
+    """
+    if board_id is None:
+        board_id = int(time.time())
     return {
         "format": 20190412,
         "title": title,
         "revision": 1,
-        "id": int(time.time()),
+        "id": board_id,
         "lists": [],
         "history": [1],
     }
 
 
+def increment_revision(board: Dict[str, Any], history_limit: Optional[int] = None):
+    """
+    Bump the board revision.
+
+    If history_limit is given, the new revision is also recorded at the front of
+    the history, keeping only that many of the most recent entries.
+    Otherwise the history is left alone.
+    """
+    board["revision"] = board["revision"] + 1
+    if history_limit is None:
+        return
+    history = [board["revision"]] + board.get("history", [])
+    board["history"] = history[:history_limit]
+
+
+def save_list(notelist: Dict[str, Any], filename: str):
+    """Write a single board list (title and notes) to a JSON file."""
+    with open(filename, "w", encoding="utf-8") as fp:
+        json.dump(notelist, fp, indent=4)
+
+
+def load_list(filename: str) -> Dict[str, Any]:
+    """Read a single board list written by save_list."""
+    with open(filename, "r", encoding="utf-8") as fp:
+        notelist = json.load(fp)
+    if "title" not in notelist or "notes" not in notelist:
+        raise RuntimeError(f"{filename} does not contain a board list")
+    return notelist
+
+
+def sort_lists(board: Dict[str, Any], key: Callable[[Dict[str, Any]], Any]):
+    """Stably sort the lists of a board using a key function on each list."""
+    board["lists"].sort(key=key)
+
+
+def reorder_lists(board: Dict[str, Any], order: List[str]):
+    """
+    Arrange the lists of a board in the given order of titles.
+
+    Lists with titles not in order keep their relative order, after the others.
+    """
+    position = {title: i for i, title in enumerate(order)}
+    sort_lists(board, lambda notelist: position.get(notelist["title"], len(order)))
+
+
+def remove_list(board: Dict[str, Any], title: str) -> Optional[Dict[str, Any]]:
+    """Remove the first list with the given title, returning it if found."""
+    for i, notelist in enumerate(board["lists"]):
+        if notelist["title"] == title:
+            return board["lists"].pop(i)
+    return None
+
+
 def _iterate_notes(board) -> Generator[Tuple[Dict, Dict], None, None]:
     log = logging.getLogger(__name__)
     # Go through all existing lists
@@ -202,13 +692,20 @@ def parse_board(
     proj: gitlab.v4.objects.Project,
     work: WorkUnitCollection,
     board: Dict[str, Any],
+    include_raw_notes: bool = False,
 ):
-    """Populate a work item collection from a nullboard export."""
+    """
+    Populate a work item collection from a nullboard export.
+
+    Raw (sub-header) notes are skipped unless include_raw_notes is True.
+    """
     log = logging.getLogger(__name__)
 
     for notelist, note in _iterate_notes(board):
+        if note.get("raw") and not include_raw_notes:
+            continue
         # For each item in those lists, extract the ref, and update the text if we can
-        refs = _REF_RE.findall(note["text"])
+        refs = find_refs(note["text"])
         log.debug("Found these refs in a note: %s", str(refs))
         if not refs:
             log.debug("Could not find any refs in '%s'", note["text"])
@@ -216,11 +713,286 @@ def parse_board(
             continue
 
         item = work.add_or_get_item_for_refs(proj, refs)
+        if item is None:
+            # None of the refs could be retrieved
+            continue
         if not item.list_name:
             # Set list
             item.list_name = notelist["title"]
 
 
+def find_note_for_workunit(
+    work: WorkUnitCollection,
+    board: Dict[str, Any],
+    item: WorkUnit,
+) -> Optional[Tuple[str, int]]:
+    """
+    Find the first note on the board belonging to the given work unit.
+
+    Returns the title of the containing list and the index of the note in it.
+    """
+    for notelist in board["lists"]:
+        for i, note in enumerate(notelist["notes"]):
+            refs = find_refs(note["text"])
+            if not refs:
+                continue
+            if any(found is item for found in work.get_items_for_refs(refs)):
+                return notelist["title"], i
+    return None
+
+
+def lists_containing_ref(board: Dict[str, Any], ref: str) -> List[str]:
+    """Return the titles of the lists with a note mentioning the ref, in board order."""
+    titles: List[str] = []
+    for notelist, note in _iterate_notes(board):
+        title = notelist["title"]
+        if title not in titles and ref in find_refs(note["text"]):
+            titles.append(title)
+    return titles
+
+
+def replace_ref(board: Dict[str, Any], old_ref: str, new_ref: str) -> int:
+    """
+    Rewrite every mention of old_ref on the board to new_ref.
+
+    Linked refs lose their link, since it points to the old item:
+    update_board will link them again.
+
+    Returns the number of note lines changed.
+    """
+    linked_re = re.compile(r"\[" + re.escape(old_ref) + r"\]\([^)\s]*\)")
+    bare_re = re.compile(re.escape(old_ref) + r"\b")
+    changed_lines = 0
+    for _, note in _iterate_notes(board):
+        lines = note["text"].split("\n")
+        for i, line in enumerate(lines):
+            new_line = bare_re.sub(new_ref, linked_re.sub(new_ref, line))
+            if new_line != line:
+                lines[i] = new_line
+                changed_lines += 1
+        note["text"] = "\n".join(lines)
+    return changed_lines
+
+
+def archive_completed(
+    work: WorkUnitCollection,
+    board: Dict[str, Any],
+    archive_list_title: str = "Archive",
+    color: Optional[str] = None,
+    include_raw_notes: bool = False,
+) -> int:
+    """
+    Move notes whose work units are all closed/merged to the archive list.
+
+    The archive list is added at the end of the board if missing.
+    If color is given, archived notes are set to that color.
+    Raw (sub-header) notes stay put unless include_raw_notes is True.
+    Returns the number of notes moved.
+    """
+    log = logging.getLogger(__name__)
+    archive_list = None
+    for notelist in board["lists"]:
+        if notelist["title"] == archive_list_title:
+            archive_list = notelist
+    if archive_list is None:
+        archive_list = {"title": archive_list_title, "notes": []}
+        board["lists"].append(archive_list)
+
+    moved = 0
+    for notelist in board["lists"]:
+        if notelist is archive_list:
+            continue
+        kept = []
+        for note in notelist["notes"]:
+            if note.get("raw") and not include_raw_notes:
+                kept.append(note)
+                continue
+            refs = find_refs(note["text"])
+            items = work.get_items_for_refs(refs) if refs else []
+            if items and all(item.is_complete for item in items):
+                log.info("Archiving completed note for %s", items[0].ref)
+                if color is not None:
+                    set_note_color(note, color)
+                archive_list["notes"].append(note)
+                moved += 1
+            else:
+                kept.append(note)
+        notelist["notes"] = kept
+    return moved
+
+
+def find_completed_lists(work: WorkUnitCollection, board: Dict[str, Any]) -> List[str]:
+    """
+    Return the titles of lists where every note with refs is for completed work.
+
+    Lists with no notes mentioning refs are left out,
+    as are lists with a note whose refs are not in the collection.
+    Raw (sub-header) notes are not considered.
+    """
+    titles = []
+    for notelist in board["lists"]:
+        any_refs = False
+        complete = True
+        for note in notelist["notes"]:
+            if note.get("raw"):
+                continue
+            refs = find_refs(note["text"])
+            if not refs:
+                continue
+            any_refs = True
+            items = work.get_items_for_refs(refs)
+            if not items or not all(item.is_complete for item in items):
+                complete = False
+                break
+        if any_refs and complete:
+            titles.append(notelist["title"])
+    return titles
+
+
+class RefStatus(Enum):
+    OK = "ok"
+    NOT_FOUND = "not found"
+    MOVED = "moved"
+
+
+@dataclass
+class ReconcileFinding:
+    """The GitLab status of one reference in one note."""
+
+    list_title: str
+    list_index: int
+    note_index: int
+    ref: str
+    status: RefStatus
+
+
+def check_ref(
+    proj: gitlab.v4.objects.Project,
+    ref: str,
+    data: Optional[Dict[str, Union[ProjectIssue, ProjectMergeRequest]]] = None,
+) -> RefStatus:
+    """Query a reference, reporting whether it still exists or has been moved."""
+    ref_type = ReferenceType.parse_short_reference(ref)
+    ref_num = int(ref[1:])
+    try:
+        if ref_type == ReferenceType.ISSUE:
+            issue = get_issue_from_data_or_project(proj, ref, ref_num, data)
+            if issue.attributes.get("moved_to_id"):
+                return RefStatus.MOVED
+        else:
+            get_mr_from_data_or_project(proj, ref, ref_num, data)
+    except gitlab.exceptions.GitlabGetError as e:
+        if e.response_code == 404:
+            return RefStatus.NOT_FOUND
+        raise
+    return RefStatus.OK
+
+
+def reconcile_board(
+    proj: gitlab.v4.objects.Project,
+    board: Dict[str, Any],
+    data: Optional[Dict[str, Union[ProjectIssue, ProjectMergeRequest]]] = None,
+) -> List[ReconcileFinding]:
+    """Check every reference on the board against GitLab."""
+    log = logging.getLogger(__name__)
+    findings = []
+    for list_index, notelist in enumerate(board["lists"]):
+        for note_index, note in enumerate(notelist["notes"]):
+            for ref in find_refs(note["text"]):
+                status = check_ref(proj, ref, data)
+                if status != RefStatus.OK:
+                    log.info(
+                        "%s in list %s is %s", ref, notelist["title"], status.value
+                    )
+                findings.append(
+                    ReconcileFinding(
+                        notelist["title"], list_index, note_index, ref, status
+                    )
+                )
+    return findings
+
+
+def flag_deleted_refs(text: str, deleted: Set[str]) -> str:
+    """
+    Put "[deleted]" after each of the given refs in the text.
+
+    A link around a deleted ref is dropped, leaving the ref and the flag.
+    find_refs skips flagged refs.
+    """
+
+    def flag_linked(match: re.Match) -> str:
+        if match.group("ref") not in deleted:
+            return match.group(0)
+        return match.group("ref")
+
+    def flag(match: re.Match) -> str:
+        ref = match.group(0)
+        if ref not in deleted or _DELETED_FLAG_RE.match(match.string, match.end()):
+            return ref
+        return f"{ref} [deleted]"
+
+    return _REF_RE.sub(flag, _LINKED_REF_RE.sub(flag_linked, text))
+
+
+def drop_deleted_notes(
+    board: Dict[str, Any], findings: List[ReconcileFinding]
+) -> List[str]:
+    """
+    Remove notes whose refs were all found deleted by reconcile_board.
+
+    Notes with some refs still present are kept, with the deleted ones flagged
+    as by flag_deleted_refs, so later parsing does not try to retrieve them.
+
+    Returns the text of the removed notes.
+    """
+    log = logging.getLogger(__name__)
+    statuses: Dict[Tuple[int, int], Dict[str, RefStatus]] = {}
+    for finding in findings:
+        key = (finding.list_index, finding.note_index)
+        statuses.setdefault(key, {})[finding.ref] = finding.status
+
+    dropped = []
+    for list_index, notelist in enumerate(board["lists"]):
+        for note_index, note in enumerate(notelist["notes"]):
+            note_statuses = statuses.get((list_index, note_index))
+            if not note_statuses:
+                continue
+            deleted = {
+                ref
+                for ref, status in note_statuses.items()
+                if status == RefStatus.NOT_FOUND
+            }
+            if not deleted:
+                continue
+            if len(deleted) == len(note_statuses):
+                log.info("Dropping note for deleted %s", ", ".join(sorted(deleted)))
+                dropped.append(note["text"])
+                mark_note_for_deletion(note)
+                continue
+            log.info("Flagging deleted %s", ", ".join(sorted(deleted)))
+            note["text"] = flag_deleted_refs(note["text"], deleted)
+
+    remove_marked_for_deletion(board)
+    return dropped
+
+
+def find_orphan_refs(work: WorkUnitCollection, board: Dict[str, Any]) -> List[str]:
+    """
+    Return refs that appear in notes but belong to no work unit.
+
+    Refs deliberately excluded through do_not_merge are not reported.
+    """
+    orphans: List[str] = []
+    for notelist in board["lists"]:
+        for note in notelist["notes"]:
+            for ref in find_refs(note["text"]):
+                if ref in work.items_by_ref or ref in work.do_not_merge:
+                    continue
+                if ref not in orphans:
+                    orphans.append(ref)
+    return orphans
+
+
 _DELETION_MARKER = "delete"
 
 
@@ -228,6 +1000,64 @@ def mark_note_for_deletion(note: Dict[str, Union[str, bool]]):
     note[_DELETION_MARKER] = True
 
 
+AUTO_NOTE_SOURCE = "auto"
+"""Value of the "source" field on notes added by update_board(mark_new_notes=True)"""
+
+PIN_MARKER = "📌"
+"""Notes starting with this are never removed as duplicates"""
+
+
+def is_note_pinned(note: Dict[str, Any]) -> bool:
+    return note["text"].lstrip().startswith(PIN_MARKER)
+
+
+def set_note_raw(note: Dict[str, Union[str, bool]], raw: bool = True):
+    """Set whether nullboard shows this note as a raw sub-header."""
+    note["raw"] = raw
+
+
+def set_note_min(note: Dict[str, Union[str, bool]], minimized: bool = True):
+    """Set whether nullboard shows this note collapsed to its first line."""
+    note["min"] = minimized
+
+
+def set_note_color(note: Dict[str, Union[str, bool]], color: Optional[str]):
+    """
+    Set or clear the color stored with this note.
+
+    Nullboard itself does not show the color, but the scripts here keep it
+    when loading and saving board JSON.
+    """
+    if color is None:
+        note.pop("color", None)
+    else:
+        note["color"] = color
+
+
+def notes_with_color(notelist: Dict[str, Any], color: str) -> List[Dict[str, Any]]:
+    """Return the notes in the list that have the given color."""
+    return [note for note in notelist["notes"] if note.get("color") == color]
+
+
+def list_sections(
+    notelist: Dict[str, Any],
+) -> List[Tuple[Optional[Dict[str, Any]], List[Dict[str, Any]]]]:
+    """
+    Group each raw (sub-header) note with the other notes following it.
+
+    Notes before the first raw note are grouped under None, if there are any.
+    """
+    sections: List[Tuple[Optional[Dict[str, Any]], List[Dict[str, Any]]]] = []
+    for note in notelist["notes"]:
+        if note.get("raw"):
+            sections.append((note, []))
+            continue
+        if not sections:
+            sections.append((None, []))
+        sections[-1][1].append(note)
+    return sections
+
+
 def remove_marked_for_deletion(board: Dict[str, Any]):
     log = logging.getLogger(__name__)
     for notelist in board["lists"]:
@@ -242,6 +1072,95 @@ def remove_marked_for_deletion(board: Dict[str, Any]):
             notelist["notes"] = newlist
 
 
+def coalesce_list_notes(
+    work: WorkUnitCollection,
+    notelist: Dict[str, Any],
+    include_raw_notes: bool = False,
+) -> int:
+    """
+    Combine notes in a list that belong to the same work unit into the first of them.
+
+    The later notes' distinct lines are appended to the first, then the later
+    notes are removed. Raw (sub-header) notes are left alone unless
+    include_raw_notes is True. Returns the number of notes removed.
+    """
+    log = logging.getLogger(__name__)
+    first_note_by_key_ref: Dict[str, Dict[str, Any]] = {}
+    removed = 0
+    for note in notelist["notes"]:
+        if note.get("raw") and not include_raw_notes:
+            continue
+        refs = find_refs(note["text"])
+        items = work.get_items_for_refs(refs) if refs else []
+        if not items:
+            continue
+        key_ref = items[0].ref
+        first_note = first_note_by_key_ref.get(key_ref)
+        if first_note is None:
+            first_note_by_key_ref[key_ref] = note
+            continue
+        lines = first_note["text"].split("\n")
+        lines.extend(line for line in note["text"].split("\n") if line not in lines)
+        first_note["text"] = "\n".join(lines)
+        mark_note_for_deletion(note)
+        removed += 1
+
+    if removed:
+        log.info("Combined %d notes into others in %s", removed, notelist["title"])
+        notelist["notes"] = [
+            note for note in notelist["notes"] if _DELETION_MARKER not in note
+        ]
+    return removed
+
+
+class PrunePolicy(Enum):
+    """Which of several notes for the same work unit survives pruning."""
+
+    KEEP_FIRST = "first"
+    KEEP_LAST = "last"
+
+
+def prune_board(
+    work: WorkUnitCollection,
+    board: Dict[str, Any],
+    policy: PrunePolicy = PrunePolicy.KEEP_FIRST,
+    include_raw_notes: bool = False,
+) -> int:
+    """
+    Remove notes that duplicate a work unit already represented elsewhere on the board.
+
+    Duplicates are found across all lists, not just within one.
+    "First" and "last" refer to board order: lists left to right, notes top to bottom.
+    Raw (sub-header) notes are left alone unless include_raw_notes is True.
+    Returns the number of notes removed.
+    """
+    notes = [
+        note
+        for _, note in _iterate_notes(board)
+        if include_raw_notes or not note.get("raw")
+    ]
+    if policy == PrunePolicy.KEEP_LAST:
+        notes.reverse()
+
+    seen: Set[str] = set()
+    removed = 0
+    for note in notes:
+        refs = find_refs(note["text"])
+        if not refs:
+            continue
+        item_refs = {item.ref for item in work.get_items_for_refs(refs)}
+        if not item_refs:
+            continue
+        if item_refs.issubset(seen) and not is_note_pinned(note):
+            mark_note_for_deletion(note)
+            removed += 1
+        seen.update(item_refs)
+
+    if removed:
+        remove_marked_for_deletion(board)
+    return removed
+
+
 def update_board(
     work: WorkUnitCollection,
     board: Dict[str, Any],
@@ -249,17 +1168,65 @@ def update_board(
     list_guesser: Callable[[WorkUnit], str] = guess_list,
     list_titles_to_skip_adding_to=None,
     project: Optional[gitlab.v4.objects.Project] = None,
+    history_limit: Optional[int] = None,
+    keep_duplicate_notes: bool = False,
+    max_new_notes: Optional[int] = None,
+    refs_placed_elsewhere: Optional[Set[str]] = None,
+    refs_to_remove: Optional[Set[str]] = None,
+    max_note_lines: Optional[int] = None,
+    max_note_chars: Optional[int] = None,
+    keep_original_text: bool = False,
+    mark_new_notes: bool = False,
+    include_raw_notes: bool = False,
+    put_refs_first: bool = False,
 ) -> bool:
     """
     Update the JSON data for a nullboard kanban board.
 
+    The work units of all refs in each note are merged into one. A note is a
+    duplicate if every ref of that merged work unit was already covered by
+    earlier notes anywhere on the board, and duplicates are removed.
+    This differs from prune_board, which neither merges work units nor looks
+    past their key refs.
+    If keep_duplicate_notes is True, they are instead kept, with their refs from
+    that work unit replaced by a pointer to the earlier card.
+    Duplicate notes starting with PIN_MARKER are always left alone.
+
+    If max_new_notes is given, at most that many notes are added for new work
+    units, lowest refs first. The rest are left for a later run.
+
+    If history_limit is given, the new revision is added to the board history,
+    which is trimmed to that many entries. Otherwise the history is left alone.
+
+    If refs_placed_elsewhere is given, work units with any of those refs are
+    treated as belonging to another board, and get no new notes here.
+    If refs_to_remove is given, existing unpinned notes for work units with any
+    of those refs are removed, and those work units get no new notes either.
+
+    If max_note_lines or max_note_chars is given, the ref lines of each new or
+    updated note are shortened to fit, as by truncate_note_text.
+
+    If keep_original_text is True, the first time a note's text is changed, the
+    text as the user left it is kept in the note's "original" field.
+
+    If mark_new_notes is True, notes added for new work units have their
+    "source" field set to AUTO_NOTE_SOURCE.
+
+    Raw (sub-header) notes are left alone unless include_raw_notes is True.
+
+    If put_refs_first is True, the lines of each existing note are reordered as
+    by refs_first before anything else is done with it.
+
     Returns True if any changes were made.
     """
     log = logging.getLogger(__name__)
 
     if project is not None:
         # First merge stuff for completeness
-        parse_board(project, work, board)
+        parse_board(project, work, board, include_raw_notes=include_raw_notes)
+
+    to_remove: Set[str] = refs_to_remove or set()
+    elsewhere: Set[str] = to_remove.union(refs_placed_elsewhere or set())
 
     # the refs for all items used to update an existing note
     existing: Set[str] = set()
@@ -274,7 +1241,19 @@ def update_board(
 
         # For each item in those lists, extract the ref, and update the text if we can
         for note in notelist["notes"]:
-            refs = _REF_RE.findall(note["text"])
+            if note.get("raw") and not include_raw_notes:
+                continue
+            if put_refs_first:
+                old_text = note["text"]
+                new_text = "\n".join(
+                    str(line) for line in refs_first(parse_note(old_text))
+                )
+                if old_text != new_text:
+                    changed = True
+                    if keep_original_text:
+                        note.setdefault("original", old_text)
+                    note["text"] = new_text
+            refs = find_refs(note["text"])
             log.debug("Extracted refs: %s", str(refs))
             if not refs:
                 # Can't find a reference to an item in the text
@@ -288,8 +1267,27 @@ def update_board(
 
             item = work.merge_many_workunits(items)
             item_refs = set(item.refs())
+            if not to_remove.isdisjoint(item_refs) and not is_note_pinned(note):
+                log.info(
+                    "Marking a card for deletion as %s is on another board", item.ref
+                )
+                deleted_any = True
+                mark_note_for_deletion(note)
+                continue
             num_refs = len(item_refs)
             num_existing_intersection = len(existing.intersection(item_refs))
+            if num_refs == num_existing_intersection and is_note_pinned(note):
+                log.info("Leaving pinned duplicate card for %s alone", item.ref)
+                continue
+            if num_refs == num_existing_intersection and keep_duplicate_notes:
+                # This is fully handled in an existing card, just point to it
+                old_text = note["text"]
+                new_text = make_secondary_note_text(old_text, item)
+                if old_text != new_text:
+                    log.info("Pointing a duplicate card to %s", item.ref)
+                    changed = True
+                    note["text"] = new_text
+                continue
             if num_refs == num_existing_intersection:
                 # This is fully handled in an existing card
                 log.info("Marking a card for deletion as it is a duplicate")
@@ -311,10 +1309,16 @@ def update_board(
             item.list_name = list_name
             old_text = note["text"]
             new_text = note_text_maker(item)
-            merged_text = merge_note(old_text, parse_note(new_text))
+            merged_text = truncate_note_text(
+                merge_note(old_text, parse_note(new_text)),
+                max_lines=max_note_lines,
+                max_chars=max_note_chars,
+            )
             if old_text != merged_text:
                 log.info("Updated text for %s", refs[0])
                 changed = True
+                if keep_original_text:
+                    note.setdefault("original", old_text)
                 note["text"] = merged_text
 
     if deleted_any:
@@ -324,13 +1328,33 @@ def update_board(
     # Decide what list to put the leftovers in
     all_new: Dict[str, List[Dict[str, str]]] = {}
 
-    for item in work.items:
-        if item.ref in existing:
-            # we already did this
-            continue
+    new_items = [
+        item
+        for item in work.items
+        if item.ref not in existing and elsewhere.isdisjoint(item.refs())
+    ]
+    if max_new_notes is not None and len(new_items) > max_new_notes:
+        # Sort so it's predictable which ones get added this time
+        new_items.sort(key=lambda item: ref_sort_key(item.key_item))
+        log.info(
+            "Deferring %d new items, only adding %d",
+            len(new_items) - max_new_notes,
+            max_new_notes,
+        )
+        new_items = new_items[:max_new_notes]
+
+    for item in new_items:
         changed = True
         log.info("New item for %s", item.title)
-        note = {"text": note_text_maker(item)}
+        note = {
+            "text": truncate_note_text(
+                note_text_maker(item),
+                max_lines=max_note_lines,
+                max_chars=max_note_chars,
+            )
+        }
+        if mark_new_notes:
+            note["source"] = AUTO_NOTE_SOURCE
         list_name = item.list_name or list_guesser(item)
         if list_name not in all_new:
             all_new[list_name] = []
@@ -359,5 +1383,167 @@ def update_board(
         board["lists"].append({"title": missing_title, "notes": all_new[missing_title]})
 
     if changed:
-        board["revision"] = board["revision"] + 1
+        increment_revision(board, history_limit)
     return changed
+
+
+def refs_on_board(work: WorkUnitCollection, board: Dict[str, Any]) -> Set[str]:
+    """Return all refs of the work units that have a non-raw note on the board."""
+    refs: Set[str] = set()
+    for _, note in _iterate_notes(board):
+        if note.get("raw"):
+            continue
+        for item in work.get_items_for_refs(find_refs(note["text"])):
+            refs.update(item.refs())
+    return refs
+
+
+def update_boards(
+    work: WorkUnitCollection,
+    boards: List[Dict[str, Any]],
+    dedupe_across_boards: bool = False,
+    project: Optional[gitlab.v4.objects.Project] = None,
+    **kwargs,
+) -> List[bool]:
+    """
+    Update several nullboard boards from one shared work unit collection.
+
+    If project is given, all boards are parsed before any is updated,
+    so work units are merged across boards.
+    Each board keeps the work units it already has notes for,
+    and new work units are only added to the first board.
+    If dedupe_across_boards is True, a work unit with notes on several boards is
+    also removed from all but the first of them.
+    Other keyword arguments are passed to update_board.
+
+    Returns whether each board was changed.
+    """
+    if project is not None:
+        include_raw_notes = kwargs.get("include_raw_notes", False)
+        for board in boards:
+            parse_board(project, work, board, include_raw_notes=include_raw_notes)
+
+    initial = [refs_on_board(work, board) for board in boards]
+    all_refs: Set[str] = set()
+    for item in work.items:
+        all_refs.update(item.refs())
+
+    placed: Set[str] = set()
+    results = []
+    for i, board in enumerate(boards):
+        if i == 0:
+            # Only what is just on other boards stays off the first board
+            elsewhere = set().union(*initial[1:]) - initial[0]
+        else:
+            # Later boards get no new notes at all
+            elsewhere = all_refs - initial[i]
+        results.append(
+            update_board(
+                work,
+                board,
+                refs_placed_elsewhere=elsewhere,
+                refs_to_remove=placed if dedupe_across_boards else None,
+                **kwargs,
+            )
+        )
+        placed.update(refs_on_board(work, board))
+    return results
+
+
+@dataclass
+class NoteChange:
+    """A note whose text differs between two versions of a board."""
+
+    list_title: str
+    old_text: Optional[str]
+    """None if the note was added"""
+
+    new_text: Optional[str]
+    """None if the note was removed"""
+
+    @property
+    def kind(self) -> str:
+        if self.old_text is None:
+            return "added"
+        if self.new_text is None:
+            return "removed"
+        return "changed"
+
+    def to_json(self) -> Dict[str, Optional[str]]:
+        return {
+            "kind": self.kind,
+            "list_title": self.list_title,
+            "old_text": self.old_text,
+            "new_text": self.new_text,
+        }
+
+
+def diff_boards(old: Dict[str, Any], new: Dict[str, Any]) -> List[NoteChange]:
+    """Compare the note text of two boards, list by list."""
+    old_lists = {notelist["title"]: notelist["notes"] for notelist in old["lists"]}
+    changes: List[NoteChange] = []
+    for notelist in new["lists"]:
+        title = notelist["title"]
+        old_texts = [note["text"] for note in old_lists.pop(title, [])]
+        new_texts = [note["text"] for note in notelist["notes"]]
+        matcher = difflib.SequenceMatcher(a=old_texts, b=new_texts, autojunk=False)
+        for tag, i1, i2, j1, j2 in matcher.get_opcodes():
+            if tag == "equal":
+                continue
+            olds: List[Optional[str]] = list(old_texts[i1:i2])
+            news: List[Optional[str]] = list(new_texts[j1:j2])
+            # Pair up replacements, padding whichever side is shorter
+            length = max(len(olds), len(news))
+            olds.extend([None] * (length - len(olds)))
+            news.extend([None] * (length - len(news)))
+            changes.extend(
+                NoteChange(title, old_text, new_text)
+                for old_text, new_text in zip(olds, news)
+            )
+
+    # Lists that disappeared entirely
+    for title, notes in old_lists.items():
+        changes.extend(NoteChange(title, note["text"], None) for note in notes)
+    return changes
+
+
+def _board_refs(board: Dict[str, Any]) -> Set[str]:
+    refs: Set[str] = set()
+    for _, note in _iterate_notes(board):
+        refs.update(find_refs(note["text"]))
+    return refs
+
+
+def ref_delta(
+    old: Dict[str, Any], new: Dict[str, Any]
+) -> Tuple[List[str], List[str]]:
+    """
+    Compare the refs mentioned on two versions of a board.
+
+    Returns the refs only in the new board and the refs only in the old board,
+    each sorted by type and number.
+    """
+    old_refs = _board_refs(old)
+    new_refs = _board_refs(new)
+
+    def sort_key(ref: str):
+        return ref[0], int(ref[1:])
+
+    return (
+        sorted(new_refs - old_refs, key=sort_key),
+        sorted(old_refs - new_refs, key=sort_key),
+    )
+
+
+def preview_changes(
+    work: WorkUnitCollection, board: Dict[str, Any], **kwargs
+) -> List[NoteChange]:
+    """
+    Run update_board on a copy of the board and report the notes it would change.
+
+    Keyword arguments are passed to update_board.
+    The board itself is left untouched, though the work unit collection may not be.
+    """
+    proposed = copy.deepcopy(board)
+    update_board(work, proposed, **kwargs)
+    return diff_boards(board, proposed)
@@ -0,0 +1,129 @@
+#!/usr/bin/env python3
+# Copyright 2022-2024, Collabora, Ltd.
+#
+# SPDX-License-Identifier: BSL-1.0
+#
+# Author: Rylie Pavlik <rylie.pavlik@collabora.com>
+"""Export a nullboard board (as updated by these scripts) to a read-only HTML page."""
+
+import json
+import logging
+import re
+from dataclasses import dataclass, field
+from typing import Dict, List, Optional
+
+from nullboard_gitlab import UNICODE_BULLET_AND_SPACE, strip_bullet
+
+# Matches the [ref](url) links and state markers written by the note text makers
+_LINK_OR_STATE_RE = re.compile(
+    r"\[(?P<text>[^\]]+)\]\((?P<url>[^)\s]+)\)|\((?P<state>CLOSED|MERGED)\)"
+)
+
+
+@dataclass
+class HtmlSegment:
+    text: str
+    url: Optional[str] = None
+    is_state: bool = False
+
+
+@dataclass
+class HtmlNote:
+    lines: List[List[HtmlSegment]] = field(default_factory=list)
+    raw: bool = False
+
+
+def _line_to_segments(line: str) -> List[HtmlSegment]:
+    segments = []
+    pos = 0
+    for match in _LINK_OR_STATE_RE.finditer(line):
+        if match.start() > pos:
+            segments.append(HtmlSegment(line[pos : match.start()]))
+        if match.group("state"):
+            segments.append(HtmlSegment(match.group("state"), is_state=True))
+        else:
+            segments.append(HtmlSegment(match.group("text"), url=match.group("url")))
+        pos = match.end()
+    if pos < len(line):
+        segments.append(HtmlSegment(line[pos:]))
+    return segments
+
+
+def _note_to_html_note(note: Dict, bullet: str = UNICODE_BULLET_AND_SPACE) -> HtmlNote:
+    lines = [strip_bullet(line, bullet) for line in note["text"].split("\n")]
+    return HtmlNote(
+        lines=[_line_to_segments(line) for line in lines if line],
+        raw=bool(note.get("raw")),
+    )
+
+
+def nb_to_html(nb_board: Dict, bullet: str = UNICODE_BULLET_AND_SPACE) -> str:
+    from jinja2 import Environment, PackageLoader, select_autoescape
+
+    env = Environment(
+        loader=PackageLoader("openxr_ops"),
+        autoescape=select_autoescape(),
+    )
+    template = env.get_template("board.html")
+    lists = [
+        (
+            nb_list["title"],
+            [_note_to_html_note(note, bullet) for note in nb_list["notes"]],
+        )
+        for nb_list in nb_board["lists"]
+    ]
+    return template.render(board_title=nb_board["title"], lists=lists)
+
+
+def main(in_filename, out_html_filename, bullet: str = UNICODE_BULLET_AND_SPACE):
+    logging.basicConfig(level=logging.INFO)
+
+    log = logging.getLogger(__name__)
+
+    log.info("Reading %s", in_filename)
+    with open(in_filename, "r", encoding="utf-8") as fp:
+        existing_board = json.load(fp)
+
+    log.info("Converting to HTML")
+    html = nb_to_html(existing_board, bullet)
+
+    log.info("Writing to %s", out_html_filename)
+    with open(out_html_filename, "w", encoding="utf-8") as fp:
+        fp.write(html)
+
+
+if __name__ == "__main__":
+    import argparse
+
+    parser = argparse.ArgumentParser()
+    parser.add_argument(
+        "input",
+        type=str,
+        nargs=1,
+        help="Input nullboard JSON file",
+    )
+    parser.add_argument(
+        "output",
+        type=str,
+        nargs="?",
+        help="Output .html filename: auto-generated if not specified",
+    )
+    parser.add_argument(
+        "--bullet",
+        type=str,
+        default=UNICODE_BULLET_AND_SPACE,
+        help="Bullet the notes were written with, removed from their lines "
+        f"(default: '{UNICODE_BULLET_AND_SPACE}')",
+    )
+    args = parser.parse_args()
+
+    input = args.input[0]
+    if args.output:
+        output = args.output
+    else:
+        output = input.replace(".nbx", ".html")
+    main(
+        input,
+        output,
+        bullet=args.bullet,
+    )
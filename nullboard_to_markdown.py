@@ -10,6 +10,8 @@ import logging
 from dataclasses import dataclass, field
 from typing import Dict, List, Optional
 
+from nullboard_gitlab import UNICODE_BULLET_AND_SPACE, strip_bullet
+
 
 @dataclass
 class CommonMarkOutline:
@@ -29,7 +31,7 @@ class CommonMarkOutline:
         return "{}\n".format("\n".join(self.lines))
 
 
-def _list_to_md(nb_list: Dict):
+def _list_to_md(nb_list: Dict, bullet: str = UNICODE_BULLET_AND_SPACE):
     outline = CommonMarkOutline()
     outline.add_item(nb_list["title"])
     outline.current_indent_level = 1
@@ -44,7 +46,7 @@ def _list_to_md(nb_list: Dict):
             outline.current_indent_level = 2
             continue
         # Just a regular note. Split up the parts, though.
-        parts = [part.strip("•").strip() for part in note_text.split("\n")]
+        parts = [strip_bullet(part, bullet) for part in note_text.split("\n")]
         main_part = parts[0]
         outline.add_item(main_part)
 
@@ -59,13 +61,13 @@ def _list_to_md(nb_list: Dict):
     return str(outline)
 
 
-def _nb_to_md(nb_board: Dict):
+def _nb_to_md(nb_board: Dict, bullet: str = UNICODE_BULLET_AND_SPACE):
     chunks = [f"# {nb_board['title']}", ""]
-    chunks.extend(_list_to_md(nb_list) for nb_list in nb_board["lists"])
+    chunks.extend(_list_to_md(nb_list, bullet) for nb_list in nb_board["lists"])
     return "\n".join(chunks)
 
 
-def main(in_filename, out_md_filename):
+def main(in_filename, out_md_filename, bullet: str = UNICODE_BULLET_AND_SPACE):
     logging.basicConfig(level=logging.INFO)
 
     log = logging.getLogger(__name__)
@@ -75,7 +77,7 @@ def main(in_filename, out_md_filename):
         existing_board = json.load(fp)
 
     log.info("Converting to Markdown/CommonMark outline")
-    md_data = _nb_to_md(existing_board)
+    md_data = _nb_to_md(existing_board, bullet)
 
     log.info("Writing to %s", out_md_filename)
     with open(out_md_filename, "w", encoding="utf-8") as fp:
@@ -100,6 +102,13 @@ if __name__ == "__main__":
         nargs="?",
         help="Output .md filename: auto-generated if not specified",
     )
+    parser.add_argument(
+        "--bullet",
+        type=str,
+        default=UNICODE_BULLET_AND_SPACE,
+        help="Bullet the notes were written with, removed from their lines "
+        f"(default: '{UNICODE_BULLET_AND_SPACE}')",
+    )
     args = parser.parse_args()
 
     input = args.input[0]
@@ -110,4 +119,5 @@ if __name__ == "__main__":
     main(
         input,
         output,
+        bullet=args.bullet,
     )
@@ -33,6 +33,8 @@ _log = logging.getLogger(__name__)
 
 INITIAL_REVIEW_COMPLETE = "initial-review-complete"
 
+RELEASE_CHECKLIST_LABEL = "Release Checklist"
+
 
 class ColumnName(Enum):
     """Board columns and their associated labels."""
@@ -317,6 +319,7 @@ class ReleaseChecklistCollection:
         checklist_factory: Optional[ReleaseChecklistFactory],
         vendor_names,
         data: Optional[dict] = None,
+        checklist_labels: Optional[List[str]] = None,
     ):
         self.proj: gitlab.v4.objects.Project = proj
         """Main project"""
@@ -330,6 +333,10 @@ class ReleaseChecklistCollection:
         self.mr_to_issue: Dict[int, str] = {}
         self.ignore_issues: Set[str] = set()
         self.include_issues: List[int] = []
+        self.checklist_labels: List[str] = checklist_labels or [
+            RELEASE_CHECKLIST_LABEL
+        ]
+        """Labels identifying release checklists in the main project"""
 
     def load_config(self, fn: str):
         with open(fn, "rb") as fp:
@@ -382,12 +389,12 @@ class ReleaseChecklistCollection:
 
         _log.info("Parsing all opened release checklists...")
 
+        # Issues with more than one of these labels get de-duplicated by _handle_issue
         queries = [
-            self.proj.issues.list(
-                labels="Release Checklist", state="opened", iterator=True
-            ),
-            self.ops_proj.issues.list(state="opened", iterator=True),
+            self.proj.issues.list(labels=label, state="opened", iterator=True)
+            for label in self.checklist_labels
         ]
+        queries.append(self.ops_proj.issues.list(state="opened", iterator=True))
 
         if deep:
             queries.append(
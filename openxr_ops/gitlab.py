@@ -8,8 +8,10 @@
 
 import os
 from dataclasses import dataclass
+from typing import Tuple
 
 import gitlab
+import gitlab.exceptions
 import gitlab.v4.objects
 from requests_cache import Optional
 
@@ -23,6 +25,23 @@ OPERATIONS_PROJECT_NAME = "openxr/openxr-operations"
 GROUP_NAME = "openxr"
 
 
+def _get_url_and_tokens() -> Tuple[Optional[str], Optional[str], Optional[str]]:
+    """Return the GitLab URL, CI job token, and private token from the environment."""
+    from dotenv import load_dotenv
+
+    load_dotenv()
+
+    url = os.environ.get("CI_API_V4_URL")
+    if not url:
+        url = os.environ.get("GL_URL")
+    if url and "v4" in url:
+        url = url.removesuffix("/api/v4")
+
+    job_token = os.environ.get("CI_JOB_TOKEN")
+    private_token = os.environ.get("GL_ACCESS_TOKEN")
+    return url, job_token, private_token
+
+
 @dataclass
 class OpenXRGitlab:
     """Objects for interacting with the private OpenXR Gitlab repos."""
@@ -35,18 +54,7 @@ class OpenXRGitlab:
 
     @classmethod
     def create(cls) -> "OpenXRGitlab":
-        from dotenv import load_dotenv
-
-        load_dotenv()
-
-        url = os.environ.get("CI_API_V4_URL")
-        if not url:
-            url = os.environ.get("GL_URL")
-        if url and "v4" in url:
-            url = url.removesuffix("/api/v4")
-
-        job_token = os.environ.get("CI_JOB_TOKEN")
-        private_token = os.environ.get("GL_ACCESS_TOKEN")
+        url, job_token, private_token = _get_url_and_tokens()
 
         group: Optional[gitlab.v4.objects.Group] = None
 
@@ -61,3 +69,44 @@ class OpenXRGitlab:
         return cls(
             gl=gl, group=group, main_proj=main_proj, operations_proj=operations_proj
         )
+
+
+def check_config() -> bool:
+    """
+    Verify the GitLab URL, credentials, and projects, printing the result of each step.
+
+    Returns True if everything checks out.
+    """
+    url, job_token, private_token = _get_url_and_tokens()
+    if not url:
+        print("FAIL: Set GL_URL (or CI_API_V4_URL) to your GitLab instance URL")
+        return False
+    print(f"OK: Using GitLab at {url}")
+
+    if private_token:
+        gl = gitlab.Gitlab(url=url, private_token=private_token)
+        try:
+            gl.auth()
+        except gitlab.exceptions.GitlabAuthenticationError:
+            print(
+                f"FAIL: {url} rejected GL_ACCESS_TOKEN: check it is valid and unexpired"
+            )
+            return False
+        assert gl.user
+        print(f"OK: Authenticated as {gl.user.username}")
+    elif job_token:
+        gl = gitlab.Gitlab(url=url, job_token=job_token)
+        print("OK: Using CI_JOB_TOKEN (cannot verify the current user)")
+    else:
+        print("FAIL: Set GL_ACCESS_TOKEN to a personal access token")
+        return False
+
+    ok = True
+    for name in (MAIN_PROJECT_NAME, OPERATIONS_PROJECT_NAME):
+        try:
+            gl.projects.get(name)
+            print(f"OK: Found project {name}")
+        except gitlab.exceptions.GitlabGetError as e:
+            print(f"FAIL: Could not access project {name}: {e}")
+            ok = False
+    return ok
@@ -0,0 +1,468 @@
+# Copyright 2026, Collabora, Ltd.
+#
+# SPDX-License-Identifier: BSL-1.0
+"""
+Tests for the work unit collection and nullboard helpers.
+
+Run with "python3 -m pytest" from the root of the repo.
+GitLab is replaced by the small fakes below, so no token or network is needed.
+"""
+
+import threading
+from typing import Dict, List, Optional
+
+import gitlab.exceptions
+import pytest
+
+from nullboard_gitlab import (
+    UNICODE_BULLET_AND_SPACE,
+    ActionKeyword,
+    NoteLine,
+    collapse_blank_runs,
+    drop_deleted_notes,
+    find_orphan_refs,
+    find_refs,
+    make_note_text,
+    merge_note,
+    parse_board,
+    parse_note,
+    parse_note_trimmed,
+    prune_board,
+    reconcile_board,
+    refs_first,
+    strip_bullet,
+    suggest_ref_fixes,
+    to_shorthand_text,
+    truncate_note_text,
+    update_board,
+    update_boards,
+)
+from work_item_and_collection import (
+    MergeTooLarge,
+    SyncWorkUnitCollection,
+    WorkUnitCollection,
+    expand_refs,
+    export_units_dot,
+)
+
+PROJECT_ID = 1
+
+
+class FakeLinks:
+    """Stands in for the links manager of an issue."""
+
+    def __init__(self, links: Optional[List["FakeItem"]] = None):
+        self.links = links or []
+        self.list_calls = 0
+
+    def list(self, **kwargs):
+        self.list_calls += 1
+        return self.links
+
+
+class FakeItem:
+    """Enough of a ProjectIssue or ProjectMergeRequest for these tests."""
+
+    def __init__(self, ref: str, state: str = "opened", **attributes):
+        self.attributes = {
+            "references": {"short": ref},
+            "iid": int(ref[1:]),
+            "project_id": PROJECT_ID,
+            "title": f"Item {ref[1:]}",
+            "web_url": f"https://gitlab.example.com/{ref[1:]}",
+            "state": state,
+            "description": "",
+            "labels": [],
+        }
+        if ref.startswith("!"):
+            self.attributes["merge_status"] = "can_be_merged"
+            self.attributes["target_branch"] = "main"
+        self.attributes.update(attributes)
+        self.links = FakeLinks()
+        self.related_mrs: List[Dict[str, int]] = []
+        self.closed_issues: List["FakeItem"] = []
+
+    def __getattr__(self, name):
+        try:
+            return self.attributes[name]
+        except KeyError:
+            raise AttributeError(name) from None
+
+    def related_merge_requests(self):
+        return self.related_mrs
+
+    def closes_issues(self, **kwargs):
+        return self.closed_issues
+
+
+class FakeManager:
+    """Stands in for the issues or mergerequests manager of a project."""
+
+    def __init__(self, items: Dict[str, FakeItem], symbol: str):
+        self.items = items
+        self.symbol = symbol
+        self.get_calls: List[int] = []
+
+    def get(self, iid: int) -> FakeItem:
+        self.get_calls.append(iid)
+        ref = f"{self.symbol}{iid}"
+        if ref not in self.items:
+            raise gitlab.exceptions.GitlabGetError("404 Not found", 404)
+        return self.items[ref]
+
+
+class FakeProject:
+    """Stands in for a GitLab project, serving only the given issues and MRs."""
+
+    id = PROJECT_ID
+
+    def __init__(self, *items: FakeItem):
+        by_ref = {item.references["short"]: item for item in items}
+        self.issues = FakeManager(by_ref, "#")
+        self.mergerequests = FakeManager(by_ref, "!")
+
+
+def make_project(*refs: str) -> FakeProject:
+    return FakeProject(*(FakeItem(ref) for ref in refs))
+
+
+def make_board(*lists) -> Dict:
+    """Make a board from (title, [note text, ...]) pairs."""
+    return {
+        "format": 20190412,
+        "title": "Test",
+        "revision": 1,
+        "id": 1,
+        "lists": [
+            {"title": title, "notes": [{"text": text} for text in notes]}
+            for title, notes in lists
+        ],
+        "history": [1],
+    }
+
+
+def note_texts(board: Dict) -> List[List[str]]:
+    return [[note["text"] for note in notelist["notes"]] for notelist in board["lists"]]
+
+
+def test_note_line_heading_and_action_keyword():
+    heading = NoteLine.parse_line("## Section")
+    assert heading.is_heading
+    assert heading.heading_level == 2
+    assert heading.ref is None
+
+    line = NoteLine.parse_line("Fixes #12 in the loader")
+    assert not line.is_heading
+    assert line.ref == "#12"
+    assert line.action_keyword() == ActionKeyword.FIXES
+
+    assert NoteLine.parse_line("See #12").action_keyword() is None
+    assert NoteLine.parse_line("fixes nothing").action_keyword() is None
+
+
+def test_add_refs_guarded_refuses_large_merges():
+    proj = make_project("#1", "#2", "#3", "#4", "#5", "#6")
+    work = WorkUnitCollection()
+    work.add_refs(proj, ["#1", "#2", "#3"])
+    work.add_refs(proj, ["#4", "#5", "#6"])
+
+    with pytest.raises(MergeTooLarge) as excinfo:
+        work.add_refs_guarded(proj, ["#3", "#4"], max_merge_size=5)
+    assert excinfo.value.would_be == 6
+    assert sorted(set(work.items[0].refs())) == ["#1", "#2", "#3"]
+    assert sorted(set(work.items[1].refs())) == ["#4", "#5", "#6"]
+
+    work.add_refs_guarded(proj, ["#3", "#4"], max_merge_size=10)
+    assert "#4" in work.items[0].refs()
+
+
+def test_truncate_note_text_under_limit_is_unchanged():
+    text = "#1 first\n• #2 second"
+    assert truncate_note_text(text, max_lines=2, max_chars=100) == text
+
+
+def test_truncate_note_text_drops_ref_lines_only():
+    text = "#1 first\n• #2 second\n• #3 third\nuser text\n• #4 fourth"
+    assert truncate_note_text(text, max_lines=3) == (
+        "#1 first\n• #2 second\n(+2 more)\nuser text"
+    )
+
+
+def test_update_board_truncation_keeps_user_text():
+    proj = make_project("#1", "#2", "#3")
+    work = WorkUnitCollection()
+    work.add_refs(proj, ["#1", "#2", "#3"])
+    board = make_board(("TODO", ["#1\nmy own note"]))
+
+    update_board(work, board, max_note_lines=2)
+    update_board(work, board, max_note_lines=2)
+
+    (text,) = note_texts(board)[0]
+    assert text.split("\n")[1:] == ["my own note", "(+2 more)"]
+
+
+def test_prune_board_keeps_first_and_pinned():
+    proj = make_project("#1")
+    work = WorkUnitCollection()
+    work.add_refs(proj, ["#1"])
+    board = make_board(("A", ["#1 first", "📌 #1 pinned"]), ("B", ["#1 again"]))
+
+    assert prune_board(work, board) == 1
+    assert note_texts(board) == [["#1 first", "📌 #1 pinned"], []]
+
+
+def test_collapse_blank_runs_and_parse_note_trimmed():
+    lines = collapse_blank_runs(parse_note("#1\n\n\n\ntext\n\n"))
+    assert [str(line) for line in lines] == ["#1", "", "text", ""]
+
+    assert [str(line) for line in parse_note_trimmed("#1\ntext\n")] == ["#1", "text"]
+    assert [str(line) for line in parse_note("#1\ntext\n")] == ["#1", "text", ""]
+
+
+def test_non_default_bullet():
+    proj = make_project("#1", "#2", "#3")
+    work = WorkUnitCollection()
+    item = work.add_refs(proj, ["#1", "#2", "#3"])
+
+    lines = make_note_text(item, bullet="> ").split("\n")
+    assert not lines[0].startswith(">")
+    assert all(line.startswith("> ") for line in lines[1:])
+    assert strip_bullet(lines[1], "> ") == lines[1][2:]
+
+    assert strip_bullet(f"{UNICODE_BULLET_AND_SPACE}text") == "text"
+    assert strip_bullet("- text") == "- text"
+
+
+def test_find_orphan_refs():
+    proj = make_project("#1")
+    work = WorkUnitCollection()
+    work.add_refs(proj, ["#1"])
+    work.do_not_merge.add("#3")
+    board = make_board(("TODO", ["#1 and #2", "#3", "#2 again"]))
+
+    assert find_orphan_refs(work, board) == ["#2"]
+
+
+def test_find_refs_expands_ranges():
+    assert find_refs("#1-3", expand_ranges=True) == ["#1", "#2", "#3"]
+    assert find_refs("#1-9999", expand_ranges=True) == ["#1"]
+    assert find_refs("#1-3") == ["#1"]
+
+
+def test_add_ref_groups_reports_outcomes():
+    proj = make_project("#1", "#2", "#3")
+    work = WorkUnitCollection()
+
+    first, second, third = work.add_ref_groups(proj, [["#1"], ["#1", "#2"], ["#3"]])
+
+    assert first.is_new and first.refs_added == 1
+    assert not second.is_new and second.refs_added == 1
+    assert second.item is first.item
+    assert third.is_new and third.item is not first.item
+
+
+def test_expand_refs_follows_links_to_depth():
+    mr = FakeItem("!10")
+    issue = FakeItem("#1")
+    linked = FakeItem("#2")
+    issue.related_mrs = [{"iid": 10, "project_id": PROJECT_ID}]
+    issue.links = FakeLinks([FakeItem("#2"), FakeItem("#99", project_id=2)])
+    mr.closed_issues = [FakeItem("#3")]
+    proj = FakeProject(issue, linked, mr, FakeItem("#3"))
+
+    assert expand_refs(proj, "#1", depth=1) == ["#1", "!10", "#2"]
+    assert expand_refs(proj, "#1", depth=2) == ["#1", "!10", "#2", "#3"]
+    assert expand_refs(proj, "#1", depth=2, max_refs=2) == ["#1", "!10"]
+
+
+def test_to_shorthand_text():
+    text = "[#12](https://gitlab.example.com/12): title\n• [!3](https://x/3): mr"
+    assert to_shorthand_text(text) == "#12: title\n• !3: mr"
+
+
+def test_update_boards_shares_work_units():
+    proj = make_project("#1", "#2", "#3")
+    work = WorkUnitCollection()
+    board_a = make_board(("TODO", ["#1"]))
+    board_b = make_board(("TODO", ["#1 and #2"]))
+    for board in (board_a, board_b):
+        parse_board(proj, work, board)
+    work.add_refs(proj, ["#3"])
+
+    assert len(work.items) == 2
+    update_boards(work, [board_a, board_b])
+
+    # The shared unit stays on both, new units only go on the first board
+    assert "#2" in note_texts(board_a)[0][0]
+    assert [find_refs(text) for text in note_texts(board_b)[0]] == [["#1", "#2"]]
+    assert any("#3" in text for texts in note_texts(board_a) for text in texts)
+
+
+def test_update_boards_dedupe_across_boards():
+    proj = make_project("#1", "#2")
+    work = WorkUnitCollection()
+    board_a = make_board(("TODO", ["#1"]))
+    board_b = make_board(("TODO", ["#1", "#2"]))
+    for board in (board_a, board_b):
+        parse_board(proj, work, board)
+
+    update_boards(work, [board_a, board_b], dedupe_across_boards=True)
+
+    assert [find_refs(text) for text in note_texts(board_a)[0]] == [["#1"]]
+    assert [find_refs(text) for text in note_texts(board_b)[0]] == [["#2"]]
+
+
+def test_sync_collection_concurrent_reads_and_writes():
+    refs = [f"#{num}" for num in range(1, 41)]
+    proj = make_project(*refs)
+    sync_work = SyncWorkUnitCollection()
+    errors = []
+
+    def read():
+        try:
+            for _ in range(200):
+                for item in sync_work.get_items_for_refs(refs):
+                    assert item.ref in refs
+        except Exception as e:
+            errors.append(e)
+
+    def write():
+        try:
+            for ref in refs:
+                sync_work.add_refs(proj, [ref])
+        except Exception as e:
+            errors.append(e)
+
+    threads = [threading.Thread(target=read) for _ in range(2)]
+    threads.append(threading.Thread(target=write))
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+
+    assert not errors
+    work = sync_work.take_collection()
+    assert sorted(item.ref for item in work.items) == sorted(refs)
+
+
+def test_unit_size_line():
+    proj = make_project("#1", "#2", "#3", "#4")
+    work = WorkUnitCollection()
+    big = work.add_refs(proj, ["#1", "#2", "#3"])
+    small = work.add_refs(proj, ["#4"])
+
+    assert make_note_text(big, show_unit_size=True).split("\n")[1] == (
+        "(3 linked items)"
+    )
+    assert "linked items" not in make_note_text(small, show_unit_size=True)
+
+    merged = merge_note(
+        "user title\n#1 old", parse_note(make_note_text(big, show_unit_size=True))
+    )
+    assert merged.split("\n")[:3] == [
+        "user title",
+        make_note_text(big).split("\n")[0],
+        "(3 linked items)",
+    ]
+
+
+def test_export_units_dot():
+    proj = make_project("#1", "#2", "#3")
+    work = WorkUnitCollection()
+    first = work.add_refs(proj, ["#1", "#2"])
+    second = work.add_refs(proj, ["#3"])
+    work.merge_workunits(first, second)
+
+    dot = export_units_dot(work)
+    assert dot.startswith("digraph work_units {")
+    assert '"#2";' in dot
+    assert '"#3" -> "#1" [style=dashed];' in dot
+
+
+def test_drop_deleted_notes():
+    proj = make_project("#2")
+    board = make_board(
+        ("TODO", ["#99 only deleted", "#2 and #99 on same line\nmore"]),
+        ("TODO", ["#2"]),
+    )
+
+    findings = reconcile_board(proj, board)
+    dropped = drop_deleted_notes(board, findings)
+
+    assert dropped == ["#99 only deleted"]
+    assert note_texts(board) == [
+        ["#2 and #99 [deleted] on same line\nmore"],
+        ["#2"],
+    ]
+    assert find_refs(note_texts(board)[0][0]) == ["#2"]
+
+    # Nothing tries to retrieve the flagged ref again
+    work = WorkUnitCollection()
+    proj.issues.get_calls.clear()
+    parse_board(proj, work, board)
+    assert 99 not in proj.issues.get_calls
+    assert not work.failed_refs
+
+
+def test_suggest_ref_fixes():
+    fixes = suggest_ref_fixes("See MR 12 and issue 7, also # 3")
+    assert [(fix.original, fix.replacement) for fix in fixes] == [
+        ("MR 12", "!12"),
+        ("issue 7", "#7"),
+        ("# 3", "#3"),
+    ]
+    assert suggest_ref_fixes("Fixes #12 and !3") == []
+    assert suggest_ref_fixes("# 3 is a heading") == []
+
+
+def test_refs_first_keeps_order_within_groups():
+    lines = refs_first(parse_note("text a\n#1\ntext b\n!2\ntext c"))
+    assert [str(line) for line in lines] == ["#1", "!2", "text a", "text b", "text c"]
+
+    proj = make_project("#1")
+    work = WorkUnitCollection()
+    work.add_refs(proj, ["#1"])
+    board = make_board(("TODO", ["words\n#1"]))
+    assert update_board(work, board, put_refs_first=True)
+    assert note_texts(board)[0][0].split("\n")[1:] == ["words"]
+
+
+def test_raw_notes_are_not_associated():
+    proj = make_project("#1", "#2")
+    work = WorkUnitCollection()
+    board = {
+        "lists": [
+            {
+                "title": "TODO",
+                "notes": [{"text": "Header about #1", "raw": True}, {"text": "#2"}],
+            }
+        ]
+    }
+
+    parse_board(proj, work, board)
+    assert [item.ref for item in work.items] == ["#2"]
+
+    parse_board(proj, work, board, include_raw_notes=True)
+    assert sorted(item.ref for item in work.items) == ["#1", "#2"]
+
+
+def test_blocked_by_annotation():
+    blocker = FakeItem("#2")
+    blocked = FakeItem("#1")
+    blocked.links = FakeLinks(
+        [
+            FakeItem("#2", link_type="is_blocked_by"),
+            FakeItem("#5", link_type="is_blocked_by"),
+        ]
+    )
+    proj = FakeProject(blocked, blocker)
+    work = WorkUnitCollection()
+    blocked_unit = work.add_refs(proj, ["#1"])
+    blocker_unit = work.add_refs(proj, ["#2"])
+
+    text = make_note_text(blocked_unit, blocked_by_work=work)
+    assert text.split("\n")[0].endswith("(blocked by #2)")
+    assert "blocked by" not in make_note_text(blocker_unit, blocked_by_work=work)
+
+    make_note_text(blocked_unit, blocked_by_work=work)
+    assert blocked.links.list_calls == 1
@@ -5,8 +5,11 @@
 #
 # Author: Rylie Pavlik <rylie.pavlik@collabora.com>
 
+import contextlib
 import dataclasses
 import logging
+import re
+import threading
 from enum import Enum
 from typing import (
     Dict,
@@ -22,11 +25,23 @@ from typing import (
 )
 
 import gitlab
+import gitlab.exceptions
 import gitlab.v4.objects
 from gitlab.v4.objects import ProjectIssue, ProjectMergeRequest
 from typing_extensions import assert_never
 
 
+# The lookbehind skips refs qualified by another project, like "openxr/openxr-sdk#12"
+_ISSUE_REF_RE = re.compile(r"(?<![\w/])(#[1-9][0-9]*)\b")
+
+# A simplified form of GitLab's default issue closing pattern, like "Closes #12"
+_CLOSING_RE = re.compile(
+    r"\b(?:clos(?:e[sd]?|ing)|fix(?:e[sd]|ing)?|resolv(?:e[sd]?|ing))\b:?"
+    r"(?P<refs>(?:[\s,]+(?:and\s+)?#[1-9][0-9]*\b)+)",
+    re.IGNORECASE,
+)
+
+
 def make_url_list_item(issue_or_mr: Union[ProjectIssue, ProjectMergeRequest]) -> str:
     return "• {}: {} {}".format(
         issue_or_mr.references["short"], issue_or_mr.title, issue_or_mr.web_url
@@ -37,6 +52,34 @@ def is_mr(issue_or_mr: Union[ProjectIssue, ProjectMergeRequest]):
     return "merge_status" in issue_or_mr.attributes
 
 
+def is_issue(issue_or_mr: Union[ProjectIssue, ProjectMergeRequest]):
+    return not is_mr(issue_or_mr)
+
+
+def is_closed_or_merged(issue_or_mr: Union[ProjectIssue, ProjectMergeRequest]):
+    return issue_or_mr.state in ("merged", "closed")
+
+
+def get_blocking_refs(issue: ProjectIssue) -> List[str]:
+    """Return the refs of open issues in the same project linked as blocking this."""
+    return [
+        f"#{link.attributes['iid']}"
+        for link in issue.links.list(iterator=True)
+        if link.attributes["project_id"] == issue.project_id
+        and link.link_type == "is_blocked_by"
+        and link.state == "opened"
+    ]
+
+
+def closed_issue_refs(mr: ProjectMergeRequest) -> Set[str]:
+    """Return the refs of the issues that an MR description says it closes."""
+    description = mr.attributes.get("description") or ""
+    refs: Set[str] = set()
+    for match in _CLOSING_RE.finditer(description):
+        refs.update(_ISSUE_REF_RE.findall(match.group("refs")))
+    return refs
+
+
 @dataclasses.dataclass
 class WorkUnit:
     key_item: Union[ProjectIssue, ProjectMergeRequest]
@@ -46,6 +89,9 @@ class WorkUnit:
 
     list_name: Optional[str] = None
 
+    tags: Set[str] = dataclasses.field(default_factory=set)
+    """User-supplied category labels, such as bugfix or feature"""
+
     @property
     def ref(self):
         return self.key_item.references["short"]
@@ -62,6 +108,10 @@ class WorkUnit:
     def is_mr(self):
         return is_mr(self.key_item)
 
+    @property
+    def is_issue(self):
+        return is_issue(self.key_item)
+
     def refs(self):
         yield self.ref
         for issue in self.issues:
@@ -69,6 +119,31 @@ class WorkUnit:
         for mr in self.mrs:
             yield mr.references["short"]
 
+    @property
+    def is_complete(self):
+        """True if every issue and MR in this work unit is done, as by is_item_done."""
+        closed_by_mrs = self._issues_closed_by_merged_mrs()
+        return all(
+            is_closed_or_merged(api_item) or get_short_ref(api_item) in closed_by_mrs
+            for api_item in self.all_issues_and_mrs()
+        )
+
+    def _issues_closed_by_merged_mrs(self) -> Set[str]:
+        refs: Set[str] = set()
+        for api_item in self.all_issues_and_mrs():
+            if is_mr(api_item) and api_item.state == "merged":
+                refs.update(closed_issue_refs(api_item))
+        return refs
+
+    def is_item_done(self, api_item: Union[ProjectIssue, ProjectMergeRequest]):
+        """
+        True if the issue or MR is closed or merged, or is an issue closed by a
+        merged MR in this work unit, even if GitLab has not closed it yet.
+        """
+        if is_closed_or_merged(api_item):
+            return True
+        return get_short_ref(api_item) in self._issues_closed_by_merged_mrs()
+
     def get_key_item_as_mr(self) -> Optional[ProjectMergeRequest]:
         if not self.is_mr:
             return None
@@ -102,6 +177,12 @@ class WorkUnit:
         yield self.key_item
         yield from self.non_key_issues_and_mrs()
 
+    def sorted_non_key_issues_and_mrs(
+        self,
+    ) -> List[Union[ProjectIssue, ProjectMergeRequest]]:
+        """Like non_key_issues_and_mrs, but issues then MRs, each in numeric order."""
+        return sorted(self.non_key_issues_and_mrs(), key=ref_sort_key)
+
 
 class ReferenceType(Enum):
     ISSUE = "#"
@@ -114,11 +195,26 @@ class ReferenceType(Enum):
                 return reftype
         raise RuntimeError(f"Cannot detect reference type of{short_ref}")
 
+    @classmethod
+    def of_api_item(
+        cls, api_item: Union[ProjectIssue, ProjectMergeRequest]
+    ) -> "ReferenceType":
+        if is_mr(api_item):
+            return cls.MERGE_REQUEST
+        return cls.ISSUE
+
 
 def get_short_ref(api_item: Union[ProjectIssue, ProjectMergeRequest]) -> str:
     return api_item.references["short"]
 
 
+def ref_sort_key(
+    api_item: Union[ProjectIssue, ProjectMergeRequest]
+) -> Tuple[bool, int]:
+    """Sort key putting issues before MRs, each in numeric order."""
+    return is_mr(api_item), int(get_short_ref(api_item)[1:])
+
+
 def get_issue_from_data_or_project(
     proj: gitlab.v4.objects.Project,
     ref: str,
@@ -141,6 +237,155 @@ def get_mr_from_data_or_project(
     return proj.mergerequests.get(ref_num)
 
 
+def _get_neighbor_refs(proj: gitlab.v4.objects.Project, ref: str) -> List[str]:
+    """Return refs of items directly related to ref, within the same project."""
+    ref_type = ReferenceType.parse_short_reference(ref)
+    ref_num = int(ref[1:])
+    neighbors = []
+    if ref_type == ReferenceType.ISSUE:
+        issue = proj.issues.get(ref_num)
+        for mr_dict in issue.related_merge_requests():
+            if mr_dict["project_id"] == proj.id:  # type: ignore
+                neighbors.append(f"!{mr_dict['iid']}")  # type: ignore
+        for link in issue.links.list(iterator=True):
+            if link.attributes["project_id"] == proj.id:
+                neighbors.append(f"#{link.attributes['iid']}")
+    elif ref_type == ReferenceType.MERGE_REQUEST:
+        mr = proj.mergerequests.get(ref_num)
+        for issue in mr.closes_issues(iterator=True):
+            if issue.attributes["project_id"] == proj.id:
+                neighbors.append(f"#{issue.attributes['iid']}")
+    else:
+        assert_never(ref_type)
+    return neighbors
+
+
+def expand_refs(
+    proj: gitlab.v4.objects.Project,
+    seed: str,
+    depth: int,
+    max_refs: int = 50,
+) -> List[str]:
+    """
+    Follow related MRs, linked issues, and issues closed by MRs out from seed.
+
+    Stops after depth hops, or once max_refs refs have been found.
+    Returns the refs found, starting with seed, nearest first.
+    """
+    log = logging.getLogger(__name__)
+    found = [seed]
+    frontier = [seed]
+    for _ in range(depth):
+        next_frontier = []
+        for ref in frontier:
+            for neighbor in _get_neighbor_refs(proj, ref):
+                if neighbor in found:
+                    continue
+                if len(found) >= max_refs:
+                    log.warning("Stopped expanding %s at %d refs", seed, max_refs)
+                    return found
+                found.append(neighbor)
+                next_frontier.append(neighbor)
+        frontier = next_frontier
+    return found
+
+
+@dataclasses.dataclass
+class DiscoveryConfig:
+    """Which issues discover_work looks for, and how."""
+
+    labels: List[str]
+    """Issues must have all of these labels"""
+
+    state: str = "opened"
+
+    required_label_set: Optional[Set[str]] = None
+    """If set, issues must also have at least one of these labels"""
+
+    include_related_mrs: bool = True
+
+    skip_refs: Set[str] = dataclasses.field(default_factory=set)
+    """Issues to leave out entirely"""
+
+
+def discover_work(
+    proj: gitlab.v4.objects.Project, config: DiscoveryConfig
+) -> List[Tuple[ProjectIssue, List[str]]]:
+    """
+    Find issues matching config, returning each with its refs.
+
+    The refs are the issue's own short ref, followed by those of its related MRs
+    if requested.
+    """
+    log = logging.getLogger(__name__)
+    results = []
+    for issue in proj.issues.list(
+        labels=config.labels, state=config.state, iterator=True
+    ):
+        proj_issue = cast(ProjectIssue, issue)
+        ref = get_short_ref(proj_issue)
+        issue_labels = set(proj_issue.attributes["labels"])
+        if config.required_label_set and not issue_labels.intersection(
+            config.required_label_set
+        ):
+            log.info(
+                "Skipping issue without a required label: %s: %s  %s",
+                ref,
+                proj_issue.title,
+                proj_issue.attributes["web_url"],
+            )
+            continue
+
+        if ref in config.skip_refs:
+            log.info(
+                "Skipping GitLab Issue Search for: %s: %s",
+                ref,
+                proj_issue.title,
+            )
+            continue
+        refs = [ref]
+        if config.include_related_mrs:
+            refs.extend(
+                mr["references"]["short"]  # type: ignore
+                for mr in proj_issue.related_merge_requests()
+            )
+        results.append((proj_issue, refs))
+    return results
+
+
+@dataclasses.dataclass
+class AddRefsOutcome:
+    """The result of adding one group of refs to a WorkUnitCollection."""
+
+    item: Optional[WorkUnit]
+    """The new or extended work unit, or None if every ref was in do_not_merge"""
+
+    is_new: bool
+    refs_added: int
+    """How many refs the work unit gained"""
+
+
+class MergeTooLarge(RuntimeError):
+    """Raised instead of growing a work unit past a requested size."""
+
+    def __init__(self, would_be: int):
+        super().__init__(f"Merge would produce a work unit with {would_be} refs")
+        self.would_be = would_be
+
+
+@dataclasses.dataclass
+class CollectionStats:
+    """Aggregate statistics for a WorkUnitCollection."""
+
+    live_units: int
+    extinct_units: int
+    """Work units that were merged into another"""
+
+    total_refs: int
+    largest_unit_size: int
+    average_unit_size: float
+
+
 @dataclasses.dataclass
 class WorkUnitCollection:
     items_by_ref: Dict[str, WorkUnit] = dataclasses.field(default_factory=dict)
@@ -149,6 +394,34 @@ class WorkUnitCollection:
     do_not_merge: Set[str] = dataclasses.field(default_factory=set)
     """Refs that should not be fully parsed/merged"""
 
+    prefetched: Dict[str, Union[ProjectIssue, ProjectMergeRequest]] = (
+        dataclasses.field(default_factory=dict)
+    )
+    """Already-retrieved issues and MRs by short ref, used when no data is passed in"""
+
+    extinct_count: int = 0
+    """Number of work units that have been merged into another"""
+
+    failed_refs: List[str] = dataclasses.field(default_factory=list)
+    """Refs that could not be retrieved and were left out"""
+
+    merged_into: Dict[str, str] = dataclasses.field(default_factory=dict)
+    """Key ref of each merged-away work unit, mapped to the key ref it merged into"""
+
+    blocking_refs: Dict[str, List[str]] = dataclasses.field(default_factory=dict)
+    """Refs blocking each issue by short ref, as by get_blocking_refs"""
+
+    def stats(self) -> CollectionStats:
+        sizes = [len(set(item.refs())) for item in self.items]
+        total_refs = sum(sizes)
+        return CollectionStats(
+            live_units=len(self.items),
+            extinct_units=self.extinct_count,
+            total_refs=total_refs,
+            largest_unit_size=max(sizes, default=0),
+            average_unit_size=total_refs / len(sizes) if sizes else 0.0,
+        )
+
     def _add_item(
         self,
         api_item: Union[ProjectIssue, ProjectMergeRequest],
@@ -181,13 +454,19 @@ class WorkUnitCollection:
             ref_type = ReferenceType.parse_short_reference(ref)
             ref_num = int(ref[1:])
             if ref_type == ReferenceType.ISSUE:
-                issue = get_issue_from_data_or_project(proj, ref, ref_num, data)
+                try:
+                    issue = get_issue_from_data_or_project(proj, ref, ref_num, data)
+                except gitlab.exceptions.GitlabGetError:
+                    log.warning("Could not get issue with ref %s", ref)
+                    self.failed_refs.append(ref)
+                    continue
                 self.add_issue_to_workunit(item, issue)
             elif ref_type == ReferenceType.MERGE_REQUEST:
                 try:
                     mr = get_mr_from_data_or_project(proj, ref, ref_num, data)
                 except:
                     log.warning("Could not get MR with ref %s", ref)
+                    self.failed_refs.append(ref)
                     continue
                 self.add_mr_to_workunit(item, mr)
             else:
@@ -215,6 +494,76 @@ class WorkUnitCollection:
             return item
         return None
 
+    def add_ref_groups(
+        self,
+        proj: gitlab.v4.objects.Project,
+        groups: Iterable[Sequence[str]],
+        data: Optional[Dict[str, Union[ProjectIssue, ProjectMergeRequest]]] = None,
+    ) -> List[AddRefsOutcome]:
+        """Add each group of refs as if by add_refs, returning the outcomes in order."""
+        outcomes = []
+        for refs in groups:
+            existing = self.get_items_for_refs(refs)
+            size_before = len(set(existing[0].refs())) if existing else 0
+            item, is_new = self._add_refs(proj, refs, data)
+            size_after = len(set(item.refs())) if item else 0
+            outcomes.append(
+                AddRefsOutcome(
+                    item=item, is_new=bool(is_new), refs_added=size_after - size_before
+                )
+            )
+        return outcomes
+
+    def add_refs_guarded(
+        self,
+        proj: gitlab.v4.objects.Project,
+        refs: Sequence[str],
+        max_merge_size: int,
+        data: Optional[Dict[str, Union[ProjectIssue, ProjectMergeRequest]]] = None,
+    ) -> Optional[WorkUnit]:
+        """
+        Like add_refs, but refuse to link the refs into existing work units if
+        the result would contain more than max_merge_size refs.
+
+        Raises MergeTooLarge without modifying the collection in that case.
+        """
+        items = self.get_items_for_refs(refs)
+        if items:
+            combined: Set[str] = set()
+            for item in items:
+                combined.update(item.refs())
+            combined.update(r for r in refs if r not in self.do_not_merge)
+            if len(combined) > max_merge_size:
+                raise MergeTooLarge(len(combined))
+        return self.add_refs(proj, refs, data)
+
+    def completed_items(self) -> List[WorkUnit]:
+        """Return the work units whose issues and MRs are all closed or merged."""
+        return [item for item in self.items if item.is_complete]
+
+    def add_tag(self, ref: str, tag: str):
+        """Tag the work unit containing ref with a category label."""
+        self.items_by_ref[ref].tags.add(tag)
+
+    def tags_for(self, ref: str) -> Set[str]:
+        """Return the category labels of the work unit containing ref, if any."""
+        item = self.items_by_ref.get(ref)
+        if item is None:
+            return set()
+        return item.tags
+
+    def get_tracked_blockers(self, issue: ProjectIssue) -> List[str]:
+        """
+        Return the refs in this collection of open issues blocking the given issue.
+
+        The links of each issue are only retrieved once, and kept in blocking_refs.
+        """
+        ref = get_short_ref(issue)
+        if ref not in self.blocking_refs:
+            self.blocking_refs[ref] = get_blocking_refs(issue)
+        blockers = self.blocking_refs[ref]
+        return [blocker for blocker in blockers if blocker in self.items_by_ref]
+
     def get_items_for_refs(self, refs: Sequence[str]) -> List[WorkUnit]:
         """Return a list of all unique WorkUnits that the refs belong to."""
         log = logging.getLogger(__name__)
@@ -258,6 +607,9 @@ class WorkUnitCollection:
     ) -> Tuple[WorkUnit, bool]:
         """Add a new item for the given refs, or extend an existing item and return None if they overlap one."""
 
+        if data is None:
+            data = self.prefetched
+
         item: Optional[WorkUnit] = None
         items = self.get_items_for_refs(refs)
         if items:
@@ -275,30 +627,34 @@ class WorkUnitCollection:
             return item, False
 
         # OK, nothing in common with existing items
-        # Make an item for the first ref
+        # Make an item for the first ref we can retrieve
         refs_filtered = [r for r in refs if r not in self.do_not_merge]
-        if not refs_filtered:
-            return item, None
-        ref = refs_filtered[0]
-        ref_type = ReferenceType.parse_short_reference(ref)
-        ref_num = int(ref[1:])
+        for i, ref in enumerate(refs_filtered):
+            ref_type = ReferenceType.parse_short_reference(ref)
+            ref_num = int(ref[1:])
 
-        if ref_type == ReferenceType.ISSUE:
-            issue = get_issue_from_data_or_project(proj, ref, ref_num, data)
-            item = self.add_issue(proj, issue, also_add_related_mrs=False)
+            try:
+                if ref_type == ReferenceType.ISSUE:
+                    issue = get_issue_from_data_or_project(proj, ref, ref_num, data)
+                    item = self.add_issue(proj, issue, also_add_related_mrs=False)
 
-        elif ref_type == ReferenceType.MERGE_REQUEST:
-            mr = get_mr_from_data_or_project(proj, ref, ref_num, data)
-            item = self.add_mr(proj, mr)
+                elif ref_type == ReferenceType.MERGE_REQUEST:
+                    mr = get_mr_from_data_or_project(proj, ref, ref_num, data)
+                    item = self.add_mr(proj, mr)
 
-        else:
-            assert_never(ref_type)
+                else:
+                    assert_never(ref_type)
+            except gitlab.exceptions.GitlabGetError:
+                logging.getLogger(__name__).warning("Could not get %s", ref)
+                self.failed_refs.append(ref)
+                continue
 
-        assert item
+            assert item
 
-        # Add subsequent refs to it
-        self._add_refs_to_workunit(proj, item, refs[1:], data)
-        return item, True
+            # Add subsequent refs to it
+            self._add_refs_to_workunit(proj, item, refs_filtered[i + 1 :], data)
+            return item, True
+        return item, None
 
     def add_issue(
         self,
@@ -368,6 +724,34 @@ class WorkUnitCollection:
         self.items_by_ref[short_ref] = item
         return True
 
+    def attach_mr_to_workunit(self, mr: ProjectMergeRequest) -> Optional[WorkUnit]:
+        """
+        Add an MR to the work unit of a tracked issue mentioned in its title or
+        description.
+
+        Returns the work unit the MR ends up in,
+        or None if it mentions no tracked issue.
+        """
+        log = logging.getLogger(__name__)
+        ref = get_short_ref(mr)
+        if ref in self.do_not_merge:
+            return None
+        if ref in self.items_by_ref:
+            return self.items_by_ref[ref]
+        text = "\n".join((mr.title, mr.attributes.get("description") or ""))
+        items = self.get_items_for_refs(_ISSUE_REF_RE.findall(text))
+        if not items:
+            return None
+        log.info("Attaching %s to the work unit of %s", ref, items[0].ref)
+        self.add_mr_to_workunit(items[0], mr)
+        return items[0]
+
+    def attach_mrs_to_workunits(
+        self, mrs: Iterable[ProjectMergeRequest]
+    ) -> List[Tuple[ProjectMergeRequest, Optional[WorkUnit]]]:
+        """Call attach_mr_to_workunit on each MR, pairing each with its work unit."""
+        return [(mr, self.attach_mr_to_workunit(mr)) for mr in mrs]
+
     def add_mr(
         self, _proj: gitlab.v4.objects.Project, mr: ProjectMergeRequest
     ) -> Optional[WorkUnit]:
@@ -382,6 +766,8 @@ class WorkUnitCollection:
 
         try:
             self.items.remove(other)
+            self.extinct_count += 1
+            self.merged_into[other.ref] = item.ref
         except ValueError:
             log.info(
                 "Could not remove item %s from list: not found, probably already removed",
@@ -402,6 +788,8 @@ class WorkUnitCollection:
         for mr in other.mrs:
             items_transfered += self.add_mr_to_workunit(item, mr)
 
+        item.tags.update(other.tags)
+
         log.debug(
             "Transfered %d items from %s to %s during merge.",
             items_transfered,
@@ -436,3 +824,108 @@ class WorkUnitCollection:
                 len(deduplicated) - 1,
             )
         return deduplicated[0]
+
+
+def _dot_escape(s: str) -> str:
+    return s.replace("\\", "\\\\").replace('"', '\\"')
+
+
+def export_units_dot(work: WorkUnitCollection) -> str:
+    """
+    Describe the work units as a Graphviz DOT graph, for debugging merges.
+
+    Each work unit is a cluster of its refs,
+    with a dashed edge from each merged-away unit's key ref to where it went.
+    """
+    lines = ["digraph work_units {"]
+    for i, item in enumerate(work.items):
+        lines.append(f"    subgraph cluster_{i} {{")
+        lines.append(f'        label="{item.ref}: {_dot_escape(item.title)}";')
+        for ref in dict.fromkeys(item.refs()):
+            lines.append(f'        "{ref}";')
+        lines.append("    }")
+    for old_ref, new_ref in work.merged_into.items():
+        lines.append(f'    "{old_ref}" -> "{new_ref}" [style=dashed];')
+    lines.append("}")
+    return "\n".join(lines)
+
+
+class _ReadWriteLock:
+    """Lock allowing many readers at once, or a single writer."""
+
+    def __init__(self):
+        self._cond = threading.Condition()
+        self._readers = 0
+        self._writing = False
+
+    @contextlib.contextmanager
+    def read(self):
+        with self._cond:
+            self._cond.wait_for(lambda: not self._writing)
+            self._readers += 1
+        try:
+            yield
+        finally:
+            with self._cond:
+                self._readers -= 1
+                self._cond.notify_all()
+
+    @contextlib.contextmanager
+    def write(self):
+        with self._cond:
+            self._cond.wait_for(lambda: not self._writing and self._readers == 0)
+            self._writing = True
+        try:
+            yield
+        finally:
+            with self._cond:
+                self._writing = False
+                self._cond.notify_all()
+
+
+class SyncWorkUnitCollection:
+    """
+    Wrapper for sharing a WorkUnitCollection between threads.
+
+    Lookups can run at the same time as each other,
+    while additions and merges wait for exclusive access.
+    """
+
+    def __init__(self, work: Optional[WorkUnitCollection] = None):
+        self._work = work if work is not None else WorkUnitCollection()
+        self._lock = _ReadWriteLock()
+
+    def take_collection(self) -> WorkUnitCollection:
+        """Return the wrapped collection, once no other thread is using this."""
+        with self._lock.write():
+            return self._work
+
+    def get_items_for_refs(self, refs: Sequence[str]) -> List[WorkUnit]:
+        with self._lock.read():
+            return self._work.get_items_for_refs(refs)
+
+    def tags_for(self, ref: str) -> Set[str]:
+        with self._lock.read():
+            return set(self._work.tags_for(ref))
+
+    def add_refs(
+        self,
+        proj: gitlab.v4.objects.Project,
+        refs: Sequence[str],
+        data: Optional[Dict[str, Union[ProjectIssue, ProjectMergeRequest]]] = None,
+    ) -> Optional[WorkUnit]:
+        with self._lock.write():
+            return self._work.add_refs(proj, refs, data)
+
+    def add_or_get_item_for_refs(
+        self,
+        proj: gitlab.v4.objects.Project,
+        refs: Sequence[str],
+        data: Optional[Dict[str, Union[ProjectIssue, ProjectMergeRequest]]] = None,
+    ) -> WorkUnit:
+        with self._lock.write():
+            return self._work.add_or_get_item_for_refs(proj, refs, data)
+
+    def merge_many_workunits(self, items: Sequence[WorkUnit]):
+        with self._lock.write():
+            return self._work.merge_many_workunits(items)